@@ -0,0 +1,92 @@
+/// The EXIF fields photo_framer cares about, read from a raw EXIF (TIFF) block.
+#[derive(Default)]
+pub struct Exif {
+    /// Raw value of the orientation tag, which is only meaningful in the range 1–8.
+    pub orientation: Option<u16>,
+    /// Image dimensions recorded by the camera or the last editor.
+    pub pixel_dimensions: Option<(u32, u32)>,
+}
+
+const ORIENTATION: u16 = 0x0112;
+const EXIF_IFD_POINTER: u16 = 0x8769;
+const PIXEL_X_DIMENSION: u16 = 0xA002;
+const PIXEL_Y_DIMENSION: u16 = 0xA003;
+
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// Calls `f` with the tag and the inline value of every SHORT or LONG entry of
+    /// the IFD at `offset`.
+    fn entries(&self, offset: usize, mut f: impl FnMut(u16, u32)) -> Option<()> {
+        let count = self.u16(offset)? as usize;
+        for i in 0..count {
+            let entry = offset + 2 + i * 12;
+            let tag = self.u16(entry)?;
+            let value = match self.u16(entry + 2)? {
+                SHORT => self.u16(entry + 8)? as u32,
+                LONG => self.u32(entry + 8)?,
+                _ => continue,
+            };
+            f(tag, value);
+        }
+        Some(())
+    }
+}
+
+/// Parses the fields of [`Exif`] out of a raw EXIF block. Missing or malformed
+/// fields are left as `None`.
+pub fn parse(data: &[u8]) -> Exif {
+    let data = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+    let little_endian = match data.get(0..4) {
+        Some(b"II*\0") => true,
+        Some(b"MM\0*") => false,
+        _ => return Exif::default(),
+    };
+    let reader = Reader {
+        data,
+        little_endian,
+    };
+    let mut exif = Exif::default();
+    let mut exif_ifd = None;
+    if let Some(ifd0) = reader.u32(4) {
+        reader.entries(ifd0 as usize, |tag, value| match tag {
+            ORIENTATION => exif.orientation = Some(value as u16),
+            EXIF_IFD_POINTER => exif_ifd = Some(value as usize),
+            _ => {}
+        });
+    }
+    if let Some(offset) = exif_ifd {
+        let (mut width, mut height) = (None, None);
+        reader.entries(offset, |tag, value| match tag {
+            PIXEL_X_DIMENSION => width = Some(value),
+            PIXEL_Y_DIMENSION => height = Some(value),
+            _ => {}
+        });
+        exif.pixel_dimensions = width.zip(height);
+    }
+    exif
+}
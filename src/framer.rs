@@ -1,45 +1,3196 @@
-use std::path::PathBuf;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, Cursor},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{self, AtomicU64},
+        mpsc,
+    },
+    thread,
+    time::{Instant, SystemTime},
+};
 
 use image::{
-    GenericImageView, ImageError, ImageReader, Rgb, RgbImage,
+    AnimationDecoder, ColorType, DynamicImage, GenericImageView, ImageDecoder, ImageError,
+    ImageFormat, ImageReader, Rgb, RgbImage, Rgba, Rgba32FImage, RgbaImage,
+    codecs::{gif::GifDecoder, jpeg::JpegEncoder, webp::WebPDecoder},
+    error::{DecodingError, ImageFormatHint, ParameterError, ParameterErrorKind},
     imageops::{FilterType, overlay},
+    metadata::Orientation,
 };
+use log::{debug, error, info, warn};
+use thiserror::Error;
+
+use crate::{exif, font, logging, lut::Lut, metadata, raw};
 
 #[derive(Clone, Copy)]
 pub enum Sizing {
     Dimensions(u32, u32),
     AspectRatio(f32, f32),
+    /// Scale both axes to a percentage of the source, without adding a border.
+    Percent(f32),
+}
+
+/// Common photographic aspect ratios considered by [`snap_ratio`], in landscape orientation.
+const STANDARD_RATIOS: [(f32, f32); 9] = [
+    (1.0, 1.0),
+    (5.0, 4.0),
+    (4.0, 3.0),
+    (7.0, 5.0),
+    (3.0, 2.0),
+    (16.0, 10.0),
+    (5.0, 3.0),
+    (16.0, 9.0),
+    (21.0, 9.0),
+];
+
+/// Maximum relative difference between a ratio and the standard ratio it is snapped to.
+const SNAP_TOLERANCE: f32 = 0.03;
+
+/// Returns the standard aspect ratio closest to `w`:`h`, if one lies within
+/// [`SNAP_TOLERANCE`]. Portrait ratios snap to the portrait version of a standard ratio.
+pub fn snap_ratio(w: f32, h: f32) -> Option<(f32, f32)> {
+    let ratio = w / h;
+    STANDARD_RATIOS
+        .iter()
+        .flat_map(|&(a, b)| [(a, b), (b, a)])
+        .map(|(a, b)| (a, b, (ratio / (a / b)).ln().abs()))
+        .filter(|(_, _, distance)| *distance <= (1.0 + SNAP_TOLERANCE).ln())
+        .min_by(|x, y| x.2.total_cmp(&y.2))
+        .map(|(a, b, _)| (a, b))
+}
+
+/// A texture that is repeated across the canvas to fill the border.
+pub struct Pattern {
+    tile: RgbImage,
+    offset: (u32, u32),
+}
+
+impl Pattern {
+    /// Loads the tile at `path`, scaling it by `scale` once up front so every
+    /// framed image reuses the same texture.
+    pub fn load(path: &PathBuf, scale: f32, offset: (u32, u32)) -> Result<Pattern, FramerError> {
+        let mut tile = ImageReader::open(path)?.decode()?.to_rgb8();
+        if scale != 1.0 {
+            let (w, h) = tile.dimensions();
+            let w = ((w as f32 * scale).round() as u32).max(1);
+            let h = ((h as f32 * scale).round() as u32).max(1);
+            tile = image::imageops::resize(&tile, w, h, FilterType::Lanczos3);
+        }
+        let (w, h) = tile.dimensions();
+        Ok(Pattern {
+            tile,
+            offset: (offset.0 % w, offset.1 % h),
+        })
+    }
+}
+
+/// A length that is either absolute or relative to the size it is part of.
+#[derive(Clone, Copy)]
+pub enum Length {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Length {
+    /// Returns the length in pixels, for a percentage out of `total` pixels.
+    pub fn resolve(&self, total: u32) -> u32 {
+        match self {
+            Length::Pixels(pixels) => *pixels,
+            Length::Percent(percent) => (total as f32 * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+/// A border of at least `pixels`, and at least `percent` of a total length.
+#[derive(Clone, Copy)]
+pub struct Margin {
+    pub pixels: u32,
+    pub percent: f32,
+}
+
+impl Margin {
+    /// Returns the larger of the floor in pixels and the percentage of `total` pixels.
+    pub fn resolve(&self, total: u32) -> u32 {
+        self.pixels
+            .max((total as f32 * self.percent / 100.0).round() as u32)
+    }
+}
+
+pub enum Background {
+    Solid(Rgb<u8>),
+    Pattern(Pattern),
+    /// The photo itself, scaled to cover the canvas and blurred with this sigma.
+    Blur(f32),
+    /// Like [`Background::Blur`], but only the bars around the photo are blurred, which
+    /// is faster and keeps the blur from reaching behind the photo.
+    BlurBars(f32),
+    /// Each bar around the photo filled with the average color of the strip along the
+    /// photo's facing edge, such as the sky above and the ground below a landscape.
+    EdgeMatch,
+    /// The solid color of the photo's `(x, y)` pixel, clamped to its edges. The
+    /// coordinates are those of the photo as it is framed, after any crop.
+    Pixel(u32, u32),
+}
+
+/// Blurs the `(x, y, width, height)` regions of `image` with `sigma`, leaving the
+/// rest as is. Each region is blurred along with the surroundings the blur reaches.
+fn blur_regions(image: &mut RgbImage, regions: &[(u32, u32, u32, u32)], sigma: f32) {
+    let source = image.clone();
+    let (image_w, image_h) = image.dimensions();
+    let margin = (sigma * 3.0).ceil() as u32;
+    for &(x, y, w, h) in regions {
+        if w == 0 || h == 0 {
+            continue;
+        }
+        let (area_x, area_y) = (x.saturating_sub(margin), y.saturating_sub(margin));
+        let area_w = (x + w + margin).min(image_w) - area_x;
+        let area_h = (y + h + margin).min(image_h) - area_y;
+        let area = image::imageops::crop_imm(&source, area_x, area_y, area_w, area_h).to_image();
+        let blurred = image::imageops::fast_blur(&area, sigma);
+        let region = image::imageops::crop_imm(&blurred, x - area_x, y - area_y, w, h);
+        image::imageops::replace(image, &*region, x as i64, y as i64);
+    }
+}
+
+/// Scales `photo` to cover `width`x`height` and crops the overflow around its center.
+fn cover(photo: &RgbImage, width: u32, height: u32, filter: ResizeFilter) -> RgbImage {
+    let (photo_w, photo_h) = photo.dimensions();
+    let scale = (width as f32 / photo_w as f32).max(height as f32 / photo_h as f32);
+    let scaled_w = ((photo_w as f32 * scale).ceil() as u32).max(width);
+    let scaled_h = ((photo_h as f32 * scale).ceil() as u32).max(height);
+    let scaled = match filter {
+        ResizeFilter::Convolution(filter) => {
+            image::imageops::resize(photo, scaled_w, scaled_h, filter)
+        }
+        ResizeFilter::Area => {
+            area_resize(&DynamicImage::ImageRgb8(photo.clone()), scaled_w, scaled_h).into_rgb8()
+        }
+    };
+    image::imageops::crop_imm(
+        &scaled,
+        (scaled_w - width) / 2,
+        (scaled_h - height) / 2,
+        width,
+        height,
+    )
+    .to_image()
+}
+
+/// The `(x, y, width, height)` bars of a `width`x`height` canvas around `photo` placed
+/// at `offset`: above, below, left and right of it. The bars above and below span the
+/// whole width, and any of them may be empty.
+fn bars(
+    width: u32,
+    height: u32,
+    photo: &RgbImage,
+    offset: (i64, i64),
+) -> [(u32, u32, u32, u32); 4] {
+    // Part of the canvas covered by the photo.
+    let (w, h) = (width as i64, height as i64);
+    let x0 = offset.0.clamp(0, w) as u32;
+    let x1 = (offset.0 + photo.width() as i64).clamp(x0 as i64, w) as u32;
+    let y0 = offset.1.clamp(0, h) as u32;
+    let y1 = (offset.1 + photo.height() as i64).clamp(y0 as i64, h) as u32;
+    [
+        (0, 0, width, y0),
+        (0, y1, width, height - y1),
+        (0, y0, x0, y1 - y0),
+        (x1, y0, width - x1, y1 - y0),
+    ]
+}
+
+/// Average color of the `(x, y, width, height)` region of `image`.
+fn average_color(image: &RgbImage, (x, y, w, h): (u32, u32, u32, u32)) -> Rgb<u8> {
+    let mut sum = [0u64; 3];
+    for py in y..y + h {
+        for px in x..x + w {
+            for (sum, channel) in sum.iter_mut().zip(image.get_pixel(px, py).0) {
+                *sum += channel as u64;
+            }
+        }
+    }
+    let count = (w as u64 * h as u64).max(1);
+    Rgb(sum.map(|sum| ((sum + count / 2) / count) as u8))
+}
+
+/// The [`Background`] behind one photo, with the solid color of [`Background::Solid`] and
+/// [`Background::Pixel`] resolved for it.
+enum Backdrop<'a> {
+    Solid(Rgb<u8>),
+    Pattern(&'a Pattern),
+    Blur(f32),
+    BlurBars(f32),
+    EdgeMatch,
+}
+
+impl Backdrop<'_> {
+    /// Builds a `width`x`height` background for `photo` placed at `offset`, scaling the
+    /// photo with `filter` where the background is made from it. `opaque` is whether
+    /// the photo hides the background behind it.
+    fn canvas(
+        &self,
+        width: u32,
+        height: u32,
+        photo: &RgbImage,
+        filter: ResizeFilter,
+        offset: (i64, i64),
+        opaque: bool,
+    ) -> RgbImage {
+        match self {
+            Backdrop::Solid(color) => RgbImage::from_pixel(width, height, *color),
+            Backdrop::Blur(sigma) => {
+                image::imageops::fast_blur(&cover(photo, width, height, filter), *sigma)
+            }
+            Backdrop::BlurBars(sigma) => {
+                if !opaque {
+                    return Backdrop::Blur(*sigma)
+                        .canvas(width, height, photo, filter, offset, opaque);
+                }
+                let mut canvas = cover(photo, width, height, filter);
+                blur_regions(&mut canvas, &bars(width, height, photo, offset), *sigma);
+                canvas
+            }
+            Backdrop::EdgeMatch => {
+                let (photo_w, photo_h) = photo.dimensions();
+                let strip_w = (photo_w / 50).max(1);
+                let strip_h = (photo_h / 50).max(1);
+                let strips = [
+                    (0, 0, photo_w, strip_h),
+                    (0, photo_h - strip_h, photo_w, strip_h),
+                    (0, 0, strip_w, photo_h),
+                    (photo_w - strip_w, 0, strip_w, photo_h),
+                ];
+                let mut canvas = RgbImage::new(width, height);
+                for (bar, strip) in bars(width, height, photo, offset).into_iter().zip(strips) {
+                    let color = average_color(photo, strip);
+                    for y in bar.1..bar.1 + bar.3 {
+                        for x in bar.0..bar.0 + bar.2 {
+                            canvas.put_pixel(x, y, color);
+                        }
+                    }
+                }
+                canvas
+            }
+            Backdrop::Pattern(pattern) => {
+                let (tile_w, tile_h) = pattern.tile.dimensions();
+                let (offset_x, offset_y) = pattern.offset;
+                RgbImage::from_fn(width, height, |x, y| {
+                    *pattern
+                        .tile
+                        .get_pixel((x + offset_x) % tile_w, (y + offset_y) % tile_h)
+                })
+            }
+        }
+    }
+}
+
+/// How the photo is resampled when it is resized.
+#[derive(Clone, Copy)]
+pub enum ResizeFilter {
+    /// One of the `image` crate's convolution filters.
+    Convolution(FilterType),
+    /// Averages the source pixels covered by each output pixel, weighted by how much of
+    /// them it covers.
+    Area,
+}
+
+/// Edge of the canvas a [`BrandBar`] runs along.
+#[derive(Clone, Copy)]
+pub enum BarPosition {
+    Top,
+    Bottom,
+}
+
+/// A solid strip across the canvas holding a logo followed by a line of text. Its
+/// contents scale with its height, so a percentage height keeps mixed-size outputs
+/// consistent.
+pub struct BrandBar {
+    pub height: Length,
+    pub position: BarPosition,
+    pub color: Rgb<u8>,
+    pub text: Option<String>,
+    pub text_color: Rgb<u8>,
+    /// Logo with its color premultiplied by alpha, as returned by [`BrandBar::load_logo`].
+    pub logo: Option<Rgba32FImage>,
+}
+
+impl BrandBar {
+    /// Loads a logo for [`BrandBar::logo`].
+    pub fn load_logo(path: &PathBuf) -> Result<Rgba32FImage, FramerError> {
+        Ok(premultiply(&ImageReader::open(path)?.decode()?).into_rgba32f())
+    }
+}
+
+/// Where the date of a [`DateStamp`] comes from.
+#[derive(Clone, Copy)]
+pub enum DateSource {
+    /// The file's creation time, or its modification time on platforms and filesystems
+    /// that don't record creation times.
+    FileTime,
+}
+
+/// A date drawn in the lower right of the border, in the built-in font.
+pub struct DateStamp {
+    pub source: DateSource,
+    /// Pattern the date is written in, where `%Y`, `%y`, `%m`, `%d`, `%H`, `%M` and `%S`
+    /// stand for the parts of the date and time in UTC, and `%%` for a `%`.
+    pub format: String,
+    pub color: Rgb<u8>,
+}
+
+/// A print sheet that each framed image is tiled onto, `columns` by `rows` times.
+pub struct Sheet {
+    /// Width of the sheet in pixels.
+    pub width: u32,
+    /// Height of the sheet in pixels.
+    pub height: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// How a solid border's color is matched to the photo.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MatchMode {
+    /// Use the chosen color for every photo.
+    Color,
+    /// Replace the chosen color with a neutral gray of the photo's average brightness
+    /// for grayscale photos.
+    Mono,
+}
+
+/// Returns the average gray of `img` if it is grayscale: stored as such, or with
+/// equal channels in a sample of its pixels.
+fn grayscale_level(img: &DynamicImage) -> Option<u8> {
+    let (w, h) = img.dimensions();
+    // About 10000 pixels spread evenly through the image.
+    let step = ((w as u64 * h as u64 / 10_000) as u32).max(1);
+    let stored_gray = !img.color().has_color();
+    let (mut sum, mut count) = (0u64, 0u64);
+    for i in (0..w as u64 * h as u64).step_by(step as usize) {
+        let [r, g, b, _] = img
+            .get_pixel((i % w as u64) as u32, (i / w as u64) as u32)
+            .0;
+        if !stored_gray && (r.abs_diff(g) > 2 || g.abs_diff(b) > 2) {
+            return None;
+        }
+        sum += g as u64;
+        count += 1;
+    }
+    Some((sum / count.max(1)) as u8)
+}
+
+/// How images that are smaller than the requested dimensions are handled.
+#[derive(Clone, Copy)]
+pub enum SmallImage {
+    /// Leave the image unframed.
+    Skip,
+    /// Enlarge the image to fit the canvas.
+    Upscale,
+    /// Place the image at its native size in the middle of the canvas.
+    Center,
+    /// Keep the image at its native size and shrink the canvas around it, keeping the
+    /// canvas' aspect ratio.
+    ShrinkCanvas,
+}
+
+/// What happens when the photo is larger than the area of an aspect ratio canvas left
+/// for it beside the margin, caption space and brand bar, so it would otherwise be
+/// clipped or covered.
+#[derive(Clone, Copy)]
+pub enum Overflow {
+    /// Scale the photo down to fit the canvas.
+    Shrink,
+    /// Grow the canvas to fit the photo, giving up the exact aspect ratio.
+    Expand,
+    /// Fail with [`FramerError::Overflow`].
+    Error,
+}
+
+/// How the EXIF orientation tag of an input is applied.
+#[derive(Clone, Copy)]
+pub enum OrientationHandling {
+    /// Keep the pixels as stored, ignoring the tag.
+    Ignore,
+    /// Rotate according to the tag, falling back to no rotation with a warning when the
+    /// tag is invalid or contradicts the recorded dimensions.
+    Lenient,
+    /// Rotate according to the tag, failing images whose tag is invalid or contradicts
+    /// the recorded dimensions.
+    Strict,
+}
+
+/// What is done with images of a batch that look like an earlier one.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DedupMode {
+    /// Frame them anyway, but report them.
+    Flag,
+    /// Leave them unframed.
+    Skip,
+}
+
+/// Order in which the files of a batch are framed.
+#[derive(Clone, Copy)]
+pub enum SortKey {
+    /// By path.
+    Name,
+    /// By modification time, oldest first.
+    Mtime,
+    /// By file size, smallest first.
+    Size,
+}
+
+/// Name of the file used to lock an output directory against concurrent runs. It is left
+/// in place after a run: deleting it could let a run lock a new file while another still
+/// holds the lock on the deleted one. Batches never frame it.
+pub const LOCK_FILE: &str = ".photo_framer.lock";
+
+/// Which frame of an animated GIF or WebP input is framed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnimationFrame {
+    First,
+    Middle,
+    Last,
+    /// Zero-based frame index.
+    Index(usize),
+}
+
+/// Extensions of the files that are framed in batch mode.
+pub const ACCEPTED_EXTENSIONS: [&str; 5] = ["gif", "jpeg", "jpg", "png", "webp"];
+
+#[derive(Debug, Error)]
+pub enum FramerError {
+    /// The image could not be read, decoded, encoded or written.
+    #[error(transparent)]
+    Image(#[from] ImageError),
+    /// A file or directory other than an image could not be read or written.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The image was deliberately left unframed.
+    #[error("skipped: {0}")]
+    Skipped(String),
+    /// The output path is the input image itself.
+    #[error("output would overwrite the source image")]
+    OverwritesSource,
+    /// The input path has no file name to name the output after.
+    #[error("input path has no file name")]
+    NoFileName,
+    /// The image is smaller than [`FramerOptions::min_input_size`] and
+    /// [`FramerOptions::strict_min_size`] is set.
+    #[error("{0}x{1} is smaller than the minimum input size")]
+    Undersized(u32, u32),
+    /// The output path is also that of this earlier file of the batch.
+    #[error("output would overwrite that of {}", .0.display())]
+    OutputCollision(PathBuf),
+    /// [`FramerOptions::crop`] extends past the edges of this `width`x`height` image.
+    #[error("crop rectangle extends past the {0}x{1} image")]
+    CropOutOfBounds(u32, u32),
+    /// The written output didn't decode back to the framed image's dimensions.
+    #[error("output failed verification: {0}")]
+    Unverified(String),
+    /// The image looks like this earlier file of the batch and [`DedupMode::Skip`] is set.
+    #[error("near duplicate of {}", .0.display())]
+    Duplicate(PathBuf),
+    /// The photo, of the first dimensions, doesn't fit the area of the second left for it
+    /// on the canvas, and [`Overflow::Error`] is set.
+    #[error(
+        "the {}x{} photo doesn't fit the {}x{} area left for it",
+        .0.0, .0.1, .1.0, .1.1
+    )]
+    Overflow((u32, u32), (u32, u32)),
+    /// The image is of a format whose support is behind a cargo feature, of the second
+    /// name, this build was made without.
+    #[error("photo_framer was built without {0} support, rebuild it with `--features {1}`")]
+    MissingFeature(&'static str, &'static str),
+    /// A line of a jobs file doesn't describe a job.
+    #[error("invalid job: {0}")]
+    InvalidJob(String),
+    /// No encoder is available for the extension of the output path.
+    #[error("no encoder is available for the output extension {0:?}")]
+    UnsupportedOutput(String),
+    /// A `.cube` file doesn't hold a 3D color lookup table.
+    #[error("invalid LUT: {0}")]
+    InvalidLut(String),
+}
+
+/// Details of a framed image, also reported through the structured log record.
+pub struct FrameDetails {
+    pub source: (u32, u32),
+    pub framed: (u32, u32),
+    /// Aspect ratio the image was framed at, as width divided by height.
+    pub ratio: f64,
+    /// The source is smaller than [`FramerOptions::min_input_size`].
+    pub undersized: bool,
+    /// Earlier file of the batch the source looks like, with [`DedupMode::Flag`].
+    pub duplicate_of: Option<PathBuf>,
+}
+
+pub struct FramerOptions {
+    pub background: Background,
+    /// Replaces a solid background's color for landscape images.
+    pub landscape_color: Option<Rgb<u8>>,
+    /// Replaces a solid background's color for portrait images.
+    pub portrait_color: Option<Rgb<u8>>,
+    pub match_mode: MatchMode,
+    /// Color transparent parts of the photo are flattened onto. When unset, they are
+    /// composited directly over the background.
+    pub flatten_color: Option<Rgb<u8>>,
+    /// Comment written into the metadata of each output, if the format has a comment field.
+    pub comment: Option<String>,
+    /// Longest side of a preview of each output embedded into its EXIF, for outputs
+    /// whose format has an EXIF thumbnail field.
+    pub embed_thumbnail: Option<u32>,
+    /// Directory outputs are written to before they are renamed into place. Defaults to
+    /// the directory of each output, which keeps the rename atomic.
+    pub temp_dir: Option<PathBuf>,
+    /// Filter used when resizing the photo.
+    pub filter: ResizeFilter,
+    /// Replaces `filter` when the photo is enlarged.
+    pub upscale_filter: Option<ResizeFilter>,
+    /// Replaces `filter` when the photo is shrunk.
+    pub downscale_filter: Option<ResizeFilter>,
+    /// Filter used when scaling the photo into a background, such as the blurred one.
+    /// The background is usually blurred afterwards, so a cheap filter is good enough.
+    pub background_filter: ResizeFilter,
+    /// Box-average large reductions down to within 2x of the target before applying
+    /// `filter`, trading a little quality for much faster downscaling.
+    pub box_prescale: bool,
+    /// Handling of images smaller than the canvas in [`Sizing::Dimensions`] mode.
+    pub small_image: SmallImage,
+    /// Keep the photo at its native size in [`Sizing::Dimensions`] mode, cropping it
+    /// around its center where it exceeds the canvas.
+    pub no_resize: bool,
+    pub orientation: OrientationHandling,
+    /// Dimensions below which an input is considered too low resolution, producing a
+    /// warning, or an error if `strict_min_size` is set.
+    pub min_input_size: Option<(u32, u32)>,
+    pub strict_min_size: bool,
+    /// Rectangle the photo is cropped to before it is framed, as `(x, y, width, height)`
+    /// measured from its top-left corner after any EXIF rotation.
+    pub crop: Option<(u32, u32, u32, u32)>,
+    /// Frame of animated inputs that is framed. Still images only have a first frame.
+    pub frame: AnimationFrame,
+    /// In [`Sizing::AspectRatio`] mode, photos whose aspect ratio differs from the
+    /// canvas by less than this percentage are cropped around their center to fill it.
+    pub snap_threshold: f32,
+    /// Most elongated aspect ratio, as long side over short side, a photo keeps in aspect
+    /// ratio mode. Longer photos are cropped around their center to it before the canvas
+    /// is padded around them.
+    pub max_aspect: Option<f32>,
+    /// What happens when the photo doesn't fit an aspect ratio canvas.
+    pub overflow: Overflow,
+    /// Widen the border so that both dimensions of the output are multiples of this.
+    pub pad_to_multiple: Option<u32>,
+    /// Smallest length of the shorter side of the framed image. Smaller framed images are
+    /// enlarged as a whole, border included, to it.
+    pub min_dimension: Option<u32>,
+    /// Sheet that copies of each framed image are tiled onto, replacing the output.
+    pub sheet: Option<Sheet>,
+    /// Fraction of the area the photo would be fitted into that it fills in each
+    /// direction, in `(0, 1]`, so the border grows with the canvas.
+    pub fill_ratio: f32,
+    /// Border kept on every side of the photo, in addition to the caption space. Its
+    /// percentage is of the longer side of the canvas in [`Sizing::Dimensions`] mode and
+    /// of the longer side of the photo otherwise, including with `uniform_size`.
+    pub margin: Option<Margin>,
+    /// Space reserved at the bottom of the canvas, for example for a caption. The photo
+    /// is centered in the area above it.
+    pub caption_space: Option<Length>,
+    /// Bar drawn over the top or bottom of the canvas, usually over space reserved with
+    /// `caption_space`. In [`Sizing::AspectRatio`] mode the photo is kept clear of it as
+    /// set by `overflow`.
+    pub brand_bar: Option<BrandBar>,
+    /// Width and color of a line drawn along the edge of the whole canvas.
+    pub edge_stroke: Option<(u32, Rgb<u8>)>,
+    pub date_stamp: Option<DateStamp>,
+    /// Color lookup table the photo is graded with.
+    pub lut: Option<Lut>,
+    /// Also grade the backgrounds and colors derived from the photo, such as a blurred
+    /// background, with [`FramerOptions::lut`].
+    pub lut_background: bool,
+    /// Text appended to the file stem of each output.
+    pub suffix: Option<String>,
+    /// Extension, and so format, of each output. Defaults to that of the input.
+    pub output_extension: Option<String>,
+    /// Only frame files with one of the [`ACCEPTED_EXTENSIONS`] in batch mode. When
+    /// disabled, every file is attempted and non-images are rejected by the decoder.
+    pub extension_filter: bool,
+    /// Order of the files of a batch. Directories are read in name order unless another
+    /// is given, while input lists keep their order unless one is given.
+    pub sort: Option<SortKey>,
+    /// Reverse `sort`, sorting by name if it is unset.
+    pub sort_descending: bool,
+    /// When a batch is written into one output directory, prefix each output with the
+    /// directories leading to its input, so same-named files of different directories
+    /// don't collide.
+    pub flatten_output: bool,
+    /// Frame every image of a batch into the same canvas, the smallest that fits the
+    /// largest input in [`Sizing::AspectRatio`] mode. Smaller images are centered at
+    /// their native size rather than enlarged.
+    pub uniform_size: bool,
+    /// Sizing of individual images by input path or file name, replacing the sizing they
+    /// would otherwise be framed with. Paths take precedence over names.
+    pub sizing_overrides: HashMap<OsString, Sizing>,
+    /// Skip images without an entry in `sizing_overrides`.
+    pub require_sizing_override: bool,
+    /// Also write a proof of each output next to it, named with a `_comparison` suffix,
+    /// showing the photo before and after framing side by side.
+    pub comparison: bool,
+    /// Decode each output after writing it and check that it has the framed dimensions.
+    pub verify: bool,
+    /// Delete outputs that fail verification.
+    pub delete_unverified: bool,
+    /// Detection of near duplicate images in a batch, by the Hamming distance between
+    /// their perceptual hashes.
+    pub dedup: Option<DedupMode>,
+    /// Largest distance, out of 64 bits, at which two images count as duplicates.
+    pub dedup_threshold: u32,
+    /// Number of images framed in parallel by [`frame_files`].
+    pub jobs: usize,
+}
+
+impl Default for FramerOptions {
+    fn default() -> Self {
+        FramerOptions {
+            background: Background::Solid(Rgb([255, 255, 255])),
+            landscape_color: None,
+            portrait_color: None,
+            match_mode: MatchMode::Color,
+            flatten_color: None,
+            comment: None,
+            embed_thumbnail: None,
+            temp_dir: None,
+            filter: ResizeFilter::Convolution(FilterType::Lanczos3),
+            upscale_filter: None,
+            downscale_filter: None,
+            background_filter: ResizeFilter::Convolution(FilterType::Triangle),
+            box_prescale: false,
+            small_image: SmallImage::Upscale,
+            no_resize: false,
+            orientation: OrientationHandling::Lenient,
+            min_input_size: None,
+            strict_min_size: false,
+            crop: None,
+            frame: AnimationFrame::First,
+            snap_threshold: 0.0,
+            max_aspect: None,
+            overflow: Overflow::Expand,
+            pad_to_multiple: None,
+            min_dimension: None,
+            fill_ratio: 1.0,
+            margin: None,
+            sheet: None,
+            caption_space: None,
+            brand_bar: None,
+            edge_stroke: None,
+            date_stamp: None,
+            lut: None,
+            lut_background: false,
+            suffix: None,
+            output_extension: None,
+            extension_filter: true,
+            sort: None,
+            sort_descending: false,
+            flatten_output: false,
+            uniform_size: false,
+            sizing_overrides: HashMap::new(),
+            require_sizing_override: false,
+            comparison: false,
+            verify: false,
+            delete_unverified: false,
+            dedup: None,
+            dedup_threshold: 6,
+            jobs: 1,
+        }
+    }
+}
+
+/// Checks an EXIF orientation tag, returning why it can't be trusted if that is the case.
+fn orientation_problem(exif: &exif::Exif, dim: (u32, u32)) -> Option<String> {
+    let value = exif.orientation?;
+    if !(1..=8).contains(&value) {
+        return Some(format!("orientation tag {value} is out of range"));
+    }
+    // Tags 5 to 8 swap the axes. If the stored pixels already have the transposed
+    // dimensions recorded by the camera, an editor has rotated them and left the tag stale.
+    let transposes = value >= 5;
+    if let Some((w, h)) = exif.pixel_dimensions
+        && transposes
+        && w != h
+        && (h, w) == dim
+    {
+        return Some(format!(
+            "orientation tag {value} would rotate pixels that are already rotated"
+        ));
+    }
+    None
+}
+
+/// Decodes the requested frame of an animated GIF or WebP image. Returns `None` for
+/// other formats and for still WebP images when their only frame is requested.
+fn decode_animation_frame(
+    input: &PathBuf,
+    format: ImageFormat,
+    frame: AnimationFrame,
+) -> Result<Option<DynamicImage>, ImageError> {
+    let missing_frame = |index: usize, count: usize| {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+            format!("frame {index} was requested, but the image has {count} frame(s)"),
+        )))
+    };
+    let reader = BufReader::new(File::open(input)?);
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader)?.into_frames().collect_frames()?,
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader)?;
+            if !decoder.has_animation() {
+                return match frame {
+                    AnimationFrame::Index(index) if index > 0 => Err(missing_frame(index, 1)),
+                    _ => Ok(None),
+                };
+            }
+            decoder.into_frames().collect_frames()?
+        }
+        _ => return Ok(None),
+    };
+    let count = frames.len();
+    let index = match frame {
+        AnimationFrame::First => 0,
+        AnimationFrame::Middle => count / 2,
+        AnimationFrame::Last => count.saturating_sub(1),
+        AnimationFrame::Index(index) => index,
+    };
+    let frame = frames
+        .into_iter()
+        .nth(index)
+        .ok_or_else(|| missing_frame(index, count))?;
+    Ok(Some(DynamicImage::ImageRgba8(frame.into_buffer())))
+}
+
+/// Decodes `input`, applying its EXIF orientation according to `options`.
+fn decode(input: &PathBuf, options: &FramerOptions) -> Result<DynamicImage, ImageError> {
+    #[cfg(feature = "raw")]
+    if raw::is_raw(input) {
+        return raw::decode(input);
+    }
+    // The format is detected from the file's contents, falling back to its extension.
+    let reader = ImageReader::open(input)?.with_guessed_format()?;
+    // The first frame is what the regular decoder returns, so animations are only
+    // decoded frame by frame when another one is requested.
+    if options.frame != AnimationFrame::First
+        && let Some(format) = reader.format()
+        && let Some(img) = decode_animation_frame(input, format, options.frame)?
+    {
+        return Ok(img);
+    }
+    let mut decoder = reader.into_decoder()?;
+    let exif = match options.orientation {
+        OrientationHandling::Ignore => None,
+        _ => decoder.exif_metadata()?.map(|data| exif::parse(&data)),
+    };
+    let mut img = DynamicImage::from_decoder(decoder)?;
+    let Some(exif) = exif else {
+        return Ok(img);
+    };
+    if let Some(problem) = orientation_problem(&exif, img.dimensions()) {
+        if let OrientationHandling::Strict = options.orientation {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::PathExtension(input.clone()),
+                problem,
+            )));
+        }
+        warn!("Not rotating {}: {problem}.", input.display());
+        return Ok(img);
+    }
+    if let Some(orientation) = exif
+        .orientation
+        .and_then(|value| Orientation::from_exif(value as u8))
+    {
+        // The rotation is baked into the pixels, and `save` writes no orientation tag.
+        img.apply_orientation(orientation);
+        if orientation != Orientation::NoTransforms {
+            debug!(
+                "Applied {orientation:?} from the EXIF orientation of {}.",
+                input.display()
+            );
+        }
+    }
+    Ok(img)
+}
+
+/// Shrinks `img` by averaging `factor`x`factor` blocks of pixels.
+fn box_downsample(img: &DynamicImage, factor: u32) -> DynamicImage {
+    let source = img.to_rgba8();
+    let (w, h) = (source.width() / factor, source.height() / factor);
+    let area = factor * factor;
+    let downsampled = RgbaImage::from_fn(w, h, |x, y| {
+        let mut sum = [0u32; 4];
+        for dy in 0..factor {
+            for dx in 0..factor {
+                let pixel = source.get_pixel(x * factor + dx, y * factor + dy);
+                for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                    *total += channel as u32;
+                }
+            }
+        }
+        image::Rgba(sum.map(|total| ((total + area / 2) / area) as u8))
+    });
+    DynamicImage::ImageRgba8(downsampled)
+}
+
+/// Source pixels covered by each of `dst` output pixels spanning `src` source pixels,
+/// along with the share of the output pixel each covers.
+fn area_weights(src: u32, dst: u32) -> Vec<Vec<(u32, f32)>> {
+    let scale = src as f64 / dst as f64;
+    (0..dst)
+        .map(|i| {
+            let (start, end) = (i as f64 * scale, (i + 1) as f64 * scale);
+            (start.floor() as u32..(end.ceil() as u32).min(src))
+                .filter_map(|j| {
+                    let overlap = end.min(j as f64 + 1.0) - start.max(j as f64);
+                    (overlap > 0.0).then_some((j, (overlap / scale) as f32))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Resizes `img` to exactly `w`x`h` with [`ResizeFilter::Area`], one axis at a time.
+fn area_resize(img: &DynamicImage, w: u32, h: u32) -> DynamicImage {
+    let source = img.to_rgba32f();
+    let average = |pixels: &mut dyn Iterator<Item = (&Rgba<f32>, f32)>| {
+        let mut sum = [0.0; 4];
+        for (pixel, weight) in pixels {
+            for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                *total += channel * weight;
+            }
+        }
+        Rgba(sum)
+    };
+    let columns = area_weights(source.width(), w);
+    let horizontal = Rgba32FImage::from_fn(w, source.height(), |x, y| {
+        average(
+            &mut columns[x as usize]
+                .iter()
+                .map(|&(j, weight)| (source.get_pixel(j, y), weight)),
+        )
+    });
+    let rows = area_weights(source.height(), h);
+    DynamicImage::ImageRgba32F(Rgba32FImage::from_fn(w, h, |x, y| {
+        average(
+            &mut rows[y as usize]
+                .iter()
+                .map(|&(j, weight)| (horizontal.get_pixel(x, j), weight)),
+        )
+    }))
+}
+
+/// Resizes `img` to fit within `w`x`h` while preserving its aspect ratio.
+fn resize(img: &DynamicImage, w: u32, h: u32, options: &FramerOptions) -> DynamicImage {
+    let (src_w, src_h) = img.dimensions();
+    let enlarges = w as f32 / src_w as f32 > 1.0 && h as f32 / src_h as f32 > 1.0;
+    let filter = if enlarges {
+        options.upscale_filter
+    } else {
+        options.downscale_filter
+    }
+    .unwrap_or(options.filter);
+    let filter = match filter {
+        ResizeFilter::Convolution(filter) => filter,
+        ResizeFilter::Area => {
+            // Rounded like `DynamicImage::resize`.
+            let ratio = (w as f64 / src_w as f64).min(h as f64 / src_h as f64);
+            let fit_w = ((src_w as f64 * ratio).round() as u32).max(1);
+            let fit_h = ((src_h as f64 * ratio).round() as u32).max(1);
+            return area_resize(img, fit_w, fit_h);
+        }
+    };
+    if options.box_prescale {
+        let factor = (src_w / w.max(1)).min(src_h / h.max(1));
+        if factor >= 2 {
+            return box_downsample(img, factor).resize(w, h, filter);
+        }
+    }
+    img.resize(w, h, filter)
+}
+
+/// Converts `img` to floating point RGBA with its color premultiplied by alpha.
+fn premultiply(img: &DynamicImage) -> DynamicImage {
+    let mut rgba = img.to_rgba32f();
+    for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3];
+        for channel in &mut pixel.0[..3] {
+            *channel *= alpha;
+        }
+    }
+    DynamicImage::ImageRgba32F(rgba)
+}
+
+/// Composites `photo`, whose color is premultiplied by alpha, over `canvas` at `offset`.
+fn composite_premultiplied(canvas: &mut RgbImage, photo: &Rgba32FImage, offset: (i64, i64)) {
+    let (canvas_w, canvas_h) = (canvas.width() as i64, canvas.height() as i64);
+    for (x, y, source) in photo.enumerate_pixels() {
+        let (x, y) = (x as i64 + offset.0, y as i64 + offset.1);
+        if x < 0 || y < 0 || x >= canvas_w || y >= canvas_h {
+            continue;
+        }
+        // Filtering can overshoot, so keep alpha and color within range.
+        let alpha = source[3].clamp(0.0, 1.0);
+        let destination = canvas.get_pixel_mut(x as u32, y as u32);
+        for (channel, source) in destination.0.iter_mut().zip(source.0) {
+            let blended = source.clamp(0.0, alpha) + *channel as f32 / 255.0 * (1.0 - alpha);
+            *channel = (blended * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Flattens `photo`, whose color is premultiplied by alpha, onto a solid `color`.
+fn flatten(photo: &Rgba32FImage, color: Rgb<u8>) -> RgbImage {
+    let mut flattened = RgbImage::from_pixel(photo.width(), photo.height(), color);
+    composite_premultiplied(&mut flattened, photo, (0, 0));
+    flattened
+}
+
+/// Builds a canvas of solid `color` with `photo` placed at `offset`. Every pixel is
+/// written once, rather than filling the whole canvas and then overlaying the photo.
+fn pad_solid(
+    photo: &RgbImage,
+    canvas_dim: (u32, u32),
+    offset: (i64, i64),
+    color: Rgb<u8>,
+) -> RgbImage {
+    let (canvas_w, canvas_h) = (canvas_dim.0 as i64, canvas_dim.1 as i64);
+    let photo_w = photo.width() as i64;
+    // Part of the canvas covered by the photo, clipped to the canvas like `overlay`.
+    let x0 = offset.0.clamp(0, canvas_w);
+    let x1 = (offset.0 + photo_w).clamp(x0, canvas_w);
+    let y0 = offset.1.clamp(0, canvas_h);
+    let y1 = (offset.1 + photo.height() as i64).clamp(y0, canvas_h);
+    let fill = |buffer: &mut Vec<u8>, pixels: i64| {
+        for _ in 0..pixels {
+            buffer.extend_from_slice(&color.0);
+        }
+    };
+    let mut buffer = Vec::with_capacity((canvas_w * canvas_h * 3) as usize);
+    for y in 0..canvas_h {
+        if y < y0 || y >= y1 {
+            fill(&mut buffer, canvas_w);
+            continue;
+        }
+        fill(&mut buffer, x0);
+        let start = (((y - offset.1) * photo_w + (x0 - offset.0)) * 3) as usize;
+        buffer.extend_from_slice(&photo.as_raw()[start..start + ((x1 - x0) * 3) as usize]);
+        fill(&mut buffer, canvas_w - x1);
+    }
+    RgbImage::from_raw(canvas_dim.0, canvas_dim.1, buffer)
+        .expect("buffer holds exactly one pixel per canvas position")
+}
+
+/// Draws a `width` pixel line of `color` just inside the edges of `canvas`.
+fn draw_edge_stroke(canvas: &mut RgbImage, width: u32, color: Rgb<u8>) {
+    let (w, h) = canvas.dimensions();
+    for (x, y, pixel) in canvas.enumerate_pixels_mut() {
+        if x < width || y < width || x >= w.saturating_sub(width) || y >= h.saturating_sub(width) {
+            *pixel = color;
+        }
+    }
+}
+
+/// Fills a rectangle of `canvas` with `color`, clipped to the rows `bounds.0..bounds.1`
+/// and the width of the canvas.
+fn fill_rect(
+    canvas: &mut RgbImage,
+    (x, y): (u32, u32),
+    (w, h): (u32, u32),
+    bounds: (u32, u32),
+    color: Rgb<u8>,
+) {
+    for y in y.max(bounds.0)..(y + h).min(bounds.1) {
+        for x in x..(x + w).min(canvas.width()) {
+            canvas.put_pixel(x, y, color);
+        }
+    }
+}
+
+/// Draws `bar` across the full width of `canvas`.
+fn draw_brand_bar(canvas: &mut RgbImage, bar: &BrandBar, options: &FramerOptions) {
+    let (canvas_w, canvas_h) = canvas.dimensions();
+    let bar_h = bar.height.resolve(canvas_h).min(canvas_h);
+    let top = match bar.position {
+        BarPosition::Top => 0,
+        BarPosition::Bottom => canvas_h - bar_h,
+    };
+    let rows = (top, top + bar_h);
+    fill_rect(canvas, (0, top), (canvas_w, bar_h), rows, bar.color);
+    // The logo and text are inset from the ends of the bar by a fifth of its height.
+    let padding = bar_h / 5;
+    let content_h = bar_h - 2 * padding;
+    let mut x = padding;
+    if let Some(logo) = &bar.logo
+        && content_h > 0
+    {
+        let logo = resize(
+            &DynamicImage::ImageRgba32F(logo.clone()),
+            canvas_w.saturating_sub(2 * padding).max(1),
+            content_h,
+            options,
+        )
+        .into_rgba32f();
+        let logo_y = top + (bar_h - logo.height()) / 2;
+        composite_premultiplied(canvas, &logo, (x as i64, logo_y as i64));
+        x += logo.width() + padding;
+    }
+    let Some(text) = &bar.text else {
+        return;
+    };
+    let available = canvas_w.saturating_sub(x + padding);
+    // Text is half as tall as the bar, unless that would overflow its width.
+    let scale = (bar_h / 2 / font::GLYPH_HEIGHT)
+        .min(available / text_units(text))
+        .max(1);
+    let text_y = top + bar_h.saturating_sub(font::GLYPH_HEIGHT * scale) / 2;
+    draw_text(canvas, text, (x, text_y), scale, rows, bar.text_color);
+}
+
+/// Width of `text` in the built-in font, in units of the glyphs' pixels. Glyphs are
+/// separated by one unit, which is scaled like the glyphs themselves.
+fn text_units(text: &str) -> u32 {
+    (text.chars().count() as u32 * (font::GLYPH_WIDTH + 1))
+        .saturating_sub(1)
+        .max(1)
+}
+
+/// Draws `text` with its top left corner at `(x, y)`, each pixel of the glyphs taking
+/// `scale`x`scale` pixels, clipped to the rows `bounds`.
+fn draw_text(
+    canvas: &mut RgbImage,
+    text: &str,
+    (x, y): (u32, u32),
+    scale: u32,
+    bounds: (u32, u32),
+    color: Rgb<u8>,
+) {
+    for (i, c) in text.chars().enumerate() {
+        let glyph_x = x + i as u32 * (font::GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in font::glyph(c).into_iter().enumerate() {
+            for column in 0..font::GLYPH_WIDTH {
+                if bits >> (font::GLYPH_WIDTH - 1 - column) & 1 == 1 {
+                    let position = (glyph_x + column * scale, y + row as u32 * scale);
+                    fill_rect(canvas, position, (scale, scale), bounds, color);
+                }
+            }
+        }
+    }
+}
+
+/// Returns the time [`DateSource`] gives for `input`.
+fn file_date(input: &Path, source: DateSource) -> io::Result<SystemTime> {
+    match source {
+        DateSource::FileTime => {
+            let metadata = fs::metadata(input)?;
+            metadata.created().or_else(|_| metadata.modified())
+        }
+    }
+}
+
+/// Writes `time` in UTC following `format`, as described by [`DateStamp::format`].
+fn format_date(time: SystemTime, format: &str) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    // Converts days since 1970-01-01 to a proleptic Gregorian date, counting in eras of
+    // 400 years from 0000-03-01 so leap days fall at the end of each year.
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&year.to_string()),
+            Some('y') => out.push_str(&format!("{:02}", year.rem_euclid(100))),
+            Some('m') => out.push_str(&format!("{month:02}")),
+            Some('d') => out.push_str(&format!("{day:02}")),
+            Some('H') => out.push_str(&format!("{:02}", secs / 3600)),
+            Some('M') => out.push_str(&format!("{:02}", secs / 60 % 60)),
+            Some('S') => out.push_str(&format!("{:02}", secs % 60)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Draws `text` in the lower right of the border of `framed`, in the strip below the
+/// photo or the one to its right, whichever fits larger text. Text is half as tall as
+/// the strip at most. Returns `false` if neither strip fits it, leaving `framed` as is.
+fn draw_date_stamp(
+    framed: &mut FrameResult,
+    text: &str,
+    color: Rgb<u8>,
+    options: &FramerOptions,
+) -> bool {
+    let (canvas_w, canvas_h) = (framed.width, framed.height);
+    // A brand bar along the bottom is drawn over the border.
+    let bottom = match &options.brand_bar {
+        Some(bar) if matches!(bar.position, BarPosition::Bottom) => {
+            canvas_h - bar.height.resolve(canvas_h).min(canvas_h)
+        }
+        _ => canvas_h,
+    };
+    let (x, y, w, h) = framed.photo;
+    let photo_right = (x + w as i64).clamp(0, canvas_w as i64) as u32;
+    let photo_bottom = (y + h as i64).clamp(0, bottom as i64) as u32;
+    let units = text_units(text);
+    // Strips as (left, top, width, height), with the text inset by half its height.
+    let strips = [
+        (0, photo_bottom, canvas_w, bottom - photo_bottom),
+        (photo_right, 0, canvas_w - photo_right, bottom),
+    ];
+    let Some((scale, (left, top, strip_w, strip_h))) = strips
+        .into_iter()
+        .map(|strip| {
+            let scale =
+                (strip.3 / (2 * font::GLYPH_HEIGHT)).min(strip.2 / (units + font::GLYPH_HEIGHT));
+            (scale, strip)
+        })
+        .max_by_key(|(scale, _)| *scale)
+        .filter(|(scale, _)| *scale > 0)
+    else {
+        return false;
+    };
+    let text_h = font::GLYPH_HEIGHT * scale;
+    let position = (
+        left + strip_w - text_h / 2 - units * scale,
+        top + strip_h - text_h / 2 - text_h,
+    );
+    draw_text(&mut framed.image, text, position, scale, (0, bottom), color);
+    true
 }
 
-pub fn frame_image(input: &PathBuf, output: &PathBuf, sizing: Sizing) -> Result<(), ImageError> {
-    let mut img = ImageReader::open(input)?.decode()?;
+/// Dimensions of the area that an image of `dim` dimensions fills `fill_ratio` of in
+/// both directions, leaving the rest as border.
+fn fill_area(dim: (u32, u32), fill_ratio: f32) -> (u32, u32) {
+    (
+        (dim.0 as f32 / fill_ratio).ceil() as u32,
+        (dim.1 as f32 / fill_ratio).ceil() as u32,
+    )
+}
+
+/// Dimensions of the central crop of an image of `dim` dimensions that has an aspect
+/// ratio of at most `max`, or `dim` itself if it already has.
+fn max_aspect_crop(dim: (u32, u32), max: f32) -> (u32, u32) {
+    (
+        dim.0.min(((dim.1 as f32 * max).round() as u32).max(1)),
+        dim.1.min(((dim.0 as f32 * max).round() as u32).max(1)),
+    )
+}
+
+/// The [`FramerOptions::margin`] around a photo of `dim` dimensions.
+fn photo_margin(dim: (u32, u32), options: &FramerOptions) -> u32 {
+    options.margin.map_or(0, |m| m.resolve(dim.0.max(dim.1)))
+}
+
+/// Dimensions of an image of `dim` dimensions with a border of `margin` on each side.
+fn with_margin(dim: (u32, u32), margin: u32) -> (u32, u32) {
+    (dim.0 + 2 * margin, dim.1 + 2 * margin)
+}
+
+/// Returns the smallest canvas with the aspect ratio `w`:`h` that contains an image
+/// of `dim` dimensions above a strip of `caption` height.
+fn aspect_canvas(dim: (u32, u32), w: f32, h: f32, caption: Option<Length>) -> (u32, u32) {
+    if caption.is_none() {
+        return aspect_canvas_without_caption(dim, w, h);
+    }
+    let needed_h = captioned_height(dim.1, caption) as f32;
+    if (dim.0 as f32 / w) < needed_h / h {
+        ((needed_h * (w / h)) as u32, needed_h as u32)
+    } else {
+        (dim.0, (dim.0 as f32 * (h / w)) as u32)
+    }
+}
+
+/// Returns the height of a canvas that fits an image of height `h` above a strip of
+/// `caption` height.
+fn captioned_height(h: u32, caption: Option<Length>) -> u32 {
+    match caption {
+        None => h,
+        Some(Length::Pixels(pixels)) => h + pixels,
+        Some(Length::Percent(percent)) => (h as f32 / (1.0 - percent / 100.0)).ceil() as u32,
+    }
+}
+
+fn aspect_canvas_without_caption(dim: (u32, u32), w: f32, h: f32) -> (u32, u32) {
+    if (dim.0 as f32 / w) < dim.1 as f32 / h {
+        // Border bars are vertical
+        ((dim.1 as f32 * (w / h)) as u32, dim.1)
+    } else {
+        // Border bars are horizontal
+        (dim.0, (dim.0 as f32 * (h / w)) as u32)
+    }
+}
+
+/// Writes `image` to `output`, in the format of [`output_format`]. The only EXIF outputs
+/// carry is that of an embedded thumbnail, whose orientation is fixed at the normal one,
+/// so pixels already rotated by [`decode`] aren't rotated again by viewers. Metadata
+/// added here must not include any other orientation.
+fn save(image: &RgbImage, output: &PathBuf, options: &FramerOptions) -> Result<(), ImageError> {
+    let format = ImageFormat::from_path(output)?;
+    debug!(
+        "Encoding {} as {format:?} with the encoder's default settings{}.",
+        output.display(),
+        if options.comment.is_some() {
+            " and a comment"
+        } else {
+            ""
+        }
+    );
+    let mut encoded = Cursor::new(Vec::new());
+    image.write_to(&mut encoded, format)?;
+    let mut encoded = encoded.into_inner();
+    if let Some(max_edge) = options.embed_thumbnail {
+        encoded = embed_thumbnail(encoded, image, format, max_edge, output)?;
+    }
+    let Some(comment) = &options.comment else {
+        return write_atomically(output, &encoded, options);
+    };
+    let tagged = metadata::insert_comment(&encoded, format, comment).unwrap_or_else(|| {
+        warn!(
+            "{} output does not support comments, writing {} without one.",
+            format.extensions_str()[0],
+            output.display()
+        );
+        encoded
+    });
+    write_atomically(output, &tagged, options)
+}
+
+/// Embeds a JPEG preview of `image`, at most `max_edge` pixels on its longer side, into
+/// the EXIF of the `encoded` output. Warns and leaves it out where `format` has no
+/// thumbnail field or the preview is too large for one.
+fn embed_thumbnail(
+    encoded: Vec<u8>,
+    image: &RgbImage,
+    format: ImageFormat,
+    max_edge: u32,
+    output: &Path,
+) -> Result<Vec<u8>, ImageError> {
+    if format != ImageFormat::Jpeg {
+        warn!(
+            "{} output does not support EXIF thumbnails, writing {} without one.",
+            format.extensions_str()[0],
+            output.display()
+        );
+        return Ok(encoded);
+    }
+    let (w, h) = image.dimensions();
+    let scale = (max_edge as f32 / w.max(h) as f32).min(1.0);
+    let thumbnail = image::imageops::thumbnail(
+        image,
+        ((w as f32 * scale).round() as u32).max(1),
+        ((h as f32 * scale).round() as u32).max(1),
+    );
+    let mut preview = Cursor::new(Vec::new());
+    thumbnail.write_with_encoder(JpegEncoder::new_with_quality(&mut preview, 75))?;
+    Ok(
+        metadata::insert_jpeg_thumbnail(&encoded, preview.get_ref()).unwrap_or_else(|| {
+            warn!(
+                "A {}x{} thumbnail is too large for EXIF, writing {} without one.",
+                thumbnail.width(),
+                thumbnail.height(),
+                output.display()
+            );
+            encoded
+        }),
+    )
+}
+
+/// Writes `data` to a temporary file in [`FramerOptions::temp_dir`], or next to
+/// `output`, and renames it to `output`, so an interrupted run never leaves a partial
+/// output behind. Renames across filesystems fall back to copying, which isn't atomic.
+fn write_atomically(output: &Path, data: &[u8], options: &FramerOptions) -> Result<(), ImageError> {
+    // Outputs of the same name in other directories can share a `temp_dir`, even at the
+    // same time, so each write gets a name of its own.
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let write = WRITES.fetch_add(1, atomic::Ordering::Relaxed);
+    let mut filename = OsString::from(".");
+    filename.push(output.file_name().unwrap_or_default());
+    filename.push(format!(".{}.{write}.tmp", std::process::id()));
+    let temp = match &options.temp_dir {
+        Some(dir) => dir.join(filename),
+        None => output.with_file_name(filename),
+    };
+    let result = fs::write(&temp, data)
+        .and_then(|()| fs::rename(&temp, output).or_else(|_| fs::copy(&temp, output).map(|_| ())));
+    // Only left behind if the rename failed.
+    let _ = fs::remove_file(&temp);
+    Ok(result?)
+}
+
+/// Decodes the written `output` again, checking it has the `expected` dimensions.
+fn verify(output: &PathBuf, expected: (u32, u32)) -> Result<(), FramerError> {
+    let decoded = ImageReader::open(output)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| FramerError::Unverified(e.to_string()))?
+        .decode()
+        .map_err(|e| FramerError::Unverified(e.to_string()))?;
+    let (w, h) = decoded.dimensions();
+    if (w, h) != expected {
+        return Err(FramerError::Unverified(format!(
+            "decoded as {w}x{h} instead of {}x{}",
+            expected.0, expected.1
+        )));
+    }
+    Ok(())
+}
+
+/// An image framed by [`frame_image_buffer`], with the dimensions and the aspect ratio
+/// (width divided by height) it was framed at.
+pub struct FrameResult {
+    pub image: RgbImage,
+    pub width: u32,
+    pub height: u32,
+    pub ratio: f64,
+    /// Position and dimensions of the photo on the canvas, as `(x, y, width, height)`.
+    pub photo: (i64, i64, u32, u32),
+}
+
+/// Frames `input` and writes the result to `output`, emitting a structured log
+/// record describing the run.
+pub fn frame_image(
+    input: &PathBuf,
+    output: &PathBuf,
+    sizing: Sizing,
+    options: &FramerOptions,
+) -> Result<FrameDetails, FramerError> {
+    frame_image_cached(input, output, sizing, options, None)
+}
+
+/// [`frame_image`], taking `input` from `cache` instead of decoding it when it's there.
+fn frame_image_cached(
+    input: &PathBuf,
+    output: &PathBuf,
+    sizing: Sizing,
+    options: &FramerOptions,
+    cache: Option<&DecodeCache>,
+) -> Result<FrameDetails, FramerError> {
+    let start = Instant::now();
+    let result = frame(input, output, sizing, options, cache);
+    let details = result.as_ref().ok();
+    let status = match &result {
+        Ok(_) => "framed",
+        Err(FramerError::Skipped(_)) => "skipped",
+        Err(_) => "failed",
+    };
+    let format = output
+        .extension()
+        .map(|ext| ext.to_string_lossy().into_owned());
+    let src_dims = details.map(|d| format!("{}x{}", d.source.0, d.source.1));
+    let out_dims = details.map(|d| format!("{}x{}", d.framed.0, d.framed.1));
+    let out_ratio = details.map(|d| d.ratio);
+    info!(
+        input:% = input.display(),
+        output:% = output.display(),
+        src_dims = src_dims.as_deref(),
+        out_dims = out_dims.as_deref(),
+        out_ratio,
+        format = format.as_deref(),
+        duration_ms = start.elapsed().as_millis() as u64,
+        status;
+        "Processed {}", input.display()
+    );
+    result
+}
+
+fn frame(
+    input: &PathBuf,
+    output: &PathBuf,
+    sizing: Sizing,
+    options: &FramerOptions,
+    cache: Option<&DecodeCache>,
+) -> Result<FrameDetails, FramerError> {
+    check_support(input)?;
+    let sizing = match options.sizing_overrides.get(input.as_os_str()).or_else(|| {
+        input
+            .file_name()
+            .and_then(|name| options.sizing_overrides.get(name))
+    }) {
+        Some(sizing) => *sizing,
+        None if options.require_sizing_override => {
+            return Err(FramerError::Skipped(
+                "no sizing is given for it".to_string(),
+            ));
+        }
+        None => sizing,
+    };
+    let mut img = match cache.and_then(|cache| cache.take(input)) {
+        Some(img) => img,
+        None => {
+            let img = decode(input, options)?;
+            if let Some(cache) = cache {
+                cache.insert(input, &img);
+            }
+            img
+        }
+    };
+    let source = img.dimensions();
+    debug!(
+        "Decoded {} as {}x{} {:?}.",
+        input.display(),
+        source.0,
+        source.1,
+        img.color()
+    );
+    match (img.color().has_alpha(), options.flatten_color) {
+        (true, Some(color)) => debug!(
+            "Flattening the transparency of {} onto {:?}.",
+            input.display(),
+            color.0
+        ),
+        (true, None) => debug!(
+            "Compositing the transparency of {} over the border.",
+            input.display()
+        ),
+        (false, _) => {}
+    }
+    if img.color() != ColorType::Rgb8 {
+        debug!(
+            "Converting {} from {:?} to Rgb8 for the output.",
+            input.display(),
+            img.color()
+        );
+    }
+    if let Some((x, y, w, h)) = options.crop {
+        if x as u64 + w as u64 > source.0 as u64 || y as u64 + h as u64 > source.1 as u64 {
+            return Err(FramerError::CropOutOfBounds(source.0, source.1));
+        }
+        img = img.crop_imm(x, y, w, h);
+    }
+    let (source_w, source_h) = img.dimensions();
+    let undersized = options
+        .min_input_size
+        .is_some_and(|(min_w, min_h)| source_w < min_w || source_h < min_h);
+    if undersized {
+        if options.strict_min_size {
+            return Err(FramerError::Undersized(source_w, source_h));
+        }
+        warn!(
+            "{} is only {source_w}x{source_h}, smaller than the minimum input size.",
+            input.display()
+        );
+    }
+    let original = options.comparison.then(|| img.clone());
+    let mut framed = frame_image_buffer(img, sizing, options)?;
+    if let Some(stamp) = &options.date_stamp {
+        let date = format_date(file_date(input, stamp.source)?, &stamp.format);
+        if !draw_date_stamp(&mut framed, &date, stamp.color, options) {
+            warn!(
+                "The border of {} has no room for its date stamp.",
+                input.display()
+            );
+        }
+    }
+    if let Some(min) = options.min_dimension {
+        let short = framed.width.min(framed.height);
+        if short < min {
+            warn!(
+                "Enlarging the {}x{} framed {} to a shorter side of {min}.",
+                framed.width,
+                framed.height,
+                input.display()
+            );
+            let scale = min as f64 / short as f64;
+            // Rounding the longer side up keeps the shorter one at `min` when fitted.
+            let w = (framed.width as f64 * scale).ceil() as u32;
+            let h = (framed.height as f64 * scale).ceil() as u32;
+            framed.image =
+                resize(&DynamicImage::ImageRgb8(framed.image), w, h, options).into_rgb8();
+            (framed.width, framed.height) = framed.image.dimensions();
+            let (x, y, photo_w, photo_h) = framed.photo;
+            framed.photo = (
+                (x as f64 * scale).round() as i64,
+                (y as f64 * scale).round() as i64,
+                (photo_w as f64 * scale).round() as u32,
+                (photo_h as f64 * scale).round() as u32,
+            );
+        }
+    }
+    if let Some(sheet) = &options.sheet {
+        framed.image = compose_sheet(&framed.image, sheet, options);
+        (framed.width, framed.height) = framed.image.dimensions();
+        framed.ratio = framed.width as f64 / framed.height as f64;
+    }
+    save(&framed.image, output, options)?;
+    if let Some(original) = original {
+        let comparison = compose_comparison(&original, &framed.image, options);
+        save(&comparison, &comparison_path(output), options)?;
+    }
+    if options.verify
+        && let Err(e) = verify(output, (framed.width, framed.height))
+    {
+        if options.delete_unverified {
+            fs::remove_file(output).map_err(ImageError::IoError)?;
+        }
+        return Err(e);
+    }
+    Ok(FrameDetails {
+        source,
+        framed: (framed.width, framed.height),
+        ratio: framed.ratio,
+        undersized,
+        duplicate_of: None,
+    })
+}
+
+/// Tiles copies of `framed` into the cells of `sheet`, each scaled to fit its cell and
+/// centered in it, with gray cut guides along the edges of the cells.
+fn compose_sheet(framed: &RgbImage, sheet: &Sheet, options: &FramerOptions) -> RgbImage {
+    let mut canvas = RgbImage::from_pixel(sheet.width, sheet.height, Rgb([255, 255, 255]));
+    let cell_w = sheet.width / sheet.columns;
+    let cell_h = sheet.height / sheet.rows;
+    let copy = resize(
+        &DynamicImage::ImageRgb8(framed.clone()),
+        cell_w,
+        cell_h,
+        options,
+    )
+    .into_rgb8();
+    let (copy_w, copy_h) = copy.dimensions();
+    for row in 0..sheet.rows {
+        for column in 0..sheet.columns {
+            let x = column * cell_w + (cell_w - copy_w) / 2;
+            let y = row * cell_h + (cell_h - copy_h) / 2;
+            overlay(&mut canvas, &copy, x as i64, y as i64);
+        }
+    }
+    let guide = (sheet.width.min(sheet.height) / 1000).max(1);
+    let color = Rgb([160, 160, 160]);
+    for column in 0..=sheet.columns {
+        let x = (column * cell_w).min(sheet.width - guide);
+        fill_rect(
+            &mut canvas,
+            (x, 0),
+            (guide, sheet.height),
+            (0, sheet.height),
+            color,
+        );
+    }
+    for row in 0..=sheet.rows {
+        let y = (row * cell_h).min(sheet.height - guide);
+        fill_rect(
+            &mut canvas,
+            (0, y),
+            (sheet.width, guide),
+            (0, sheet.height),
+            color,
+        );
+    }
+    canvas
+}
+
+/// Places `original`, scaled to the height of `framed`, to the left of `framed`,
+/// separated by a gray divider.
+fn compose_comparison(
+    original: &DynamicImage,
+    framed: &RgbImage,
+    options: &FramerOptions,
+) -> RgbImage {
+    let height = framed.height();
+    let original = if original.color().has_alpha() {
+        // Transparency shows the color it's flattened onto when framed, or white behind
+        // backgrounds without a single color.
+        let matte = match (options.flatten_color, backdrop(original, options)) {
+            (Some(color), _) | (None, Backdrop::Solid(color)) => color,
+            _ => Rgb([255, 255, 255]),
+        };
+        let premultiplied = resize(&premultiply(original), u32::MAX, height, options);
+        flatten(&premultiplied.into_rgba32f(), matte)
+    } else {
+        resize(original, u32::MAX, height, options).into_rgb8()
+    };
+    let divider = (height / 100).max(2);
+    let mut comparison = RgbImage::from_pixel(
+        original.width() + divider + framed.width(),
+        height,
+        Rgb([128, 128, 128]),
+    );
+    overlay(
+        &mut comparison,
+        &original,
+        0,
+        (height as i64 - original.height() as i64) / 2,
+    );
+    overlay(
+        &mut comparison,
+        framed,
+        (original.width() + divider) as i64,
+        0,
+    );
+    comparison
+}
+
+/// The [`FramerOptions::background`] behind `img`.
+fn backdrop<'a>(img: &DynamicImage, options: &'a FramerOptions) -> Backdrop<'a> {
+    let orientation_color = match img.width().cmp(&img.height()) {
+        Ordering::Greater => options.landscape_color,
+        Ordering::Less => options.portrait_color,
+        Ordering::Equal => None,
+    };
+    match &options.background {
+        Background::Solid(color) => {
+            let mono_color = match options.match_mode {
+                MatchMode::Mono => grayscale_level(img).map(|level| Rgb([level; 3])),
+                _ => None,
+            };
+            Backdrop::Solid(mono_color.or(orientation_color).unwrap_or(*color))
+        }
+        Background::Pixel(x, y) => {
+            let (w, h) = img.dimensions();
+            let [r, g, b, _] = img.get_pixel((*x).min(w - 1), (*y).min(h - 1)).0;
+            Backdrop::Solid(Rgb([r, g, b]))
+        }
+        Background::Pattern(pattern) => Backdrop::Pattern(pattern),
+        Background::Blur(sigma) => Backdrop::Blur(*sigma),
+        Background::BlurBars(sigma) => Backdrop::BlurBars(*sigma),
+        Background::EdgeMatch => Backdrop::EdgeMatch,
+    }
+}
+
+/// Path of the comparison written for `output`: its name with a `_comparison` suffix.
+fn comparison_path(output: &Path) -> PathBuf {
+    let mut filename = output.file_stem().unwrap_or_default().to_os_string();
+    filename.push("_comparison");
+    if let Some(extension) = output.extension() {
+        filename.push(".");
+        filename.push(extension);
+    }
+    output.with_file_name(filename)
+}
+
+/// Frames an already decoded image without writing it anywhere.
+pub fn frame_image_buffer(
+    mut img: DynamicImage,
+    sizing: Sizing,
+    options: &FramerOptions,
+) -> Result<FrameResult, FramerError> {
+    // Without `lut_background`, the photo is only graded once its background is made.
+    let photo_lut = match &options.lut {
+        Some(lut) if options.lut_background => {
+            img = lut.apply(&img);
+            None
+        }
+        lut => lut.as_ref(),
+    };
+    let backdrop = backdrop(&img, options);
+    // Images with transparency are resized and composited with premultiplied alpha, so
+    // the color of transparent pixels doesn't bleed into the visible edges.
+    let has_alpha = img.color().has_alpha();
+    if has_alpha {
+        img = premultiply(&img);
+    }
     let mut dim = img.dimensions();
-    let mut background_image = match sizing {
+    let caption = options.caption_space;
+    let margin;
+    let canvas_dim = match sizing {
         Sizing::Dimensions(w, h) => {
-            img = img.resize(w, h, FilterType::Lanczos3);
-            dim = img.dimensions();
-            RgbImage::from_pixel(w, h, Rgb([255, 255, 255]))
+            let caption_h = caption.map_or(0, |caption| caption.resolve(h)).min(h);
+            // A uniform canvas is that of the largest photo framed at an aspect ratio,
+            // so each photo is cropped and bordered as it would be there.
+            margin = if options.uniform_size {
+                if let Some(max) = options.max_aspect {
+                    let (crop_w, crop_h) = max_aspect_crop(dim, max);
+                    if (crop_w, crop_h) != dim {
+                        img = img.crop_imm(
+                            (dim.0 - crop_w) / 2,
+                            (dim.1 - crop_h) / 2,
+                            crop_w,
+                            crop_h,
+                        );
+                        dim = img.dimensions();
+                    }
+                }
+                photo_margin(dim, options)
+            } else {
+                options.margin.map_or(0, |m| m.resolve(w.max(h)))
+            };
+            // Area of the canvas the photo is fitted into.
+            let fit_w = ((w.saturating_sub(2 * margin) as f32 * options.fill_ratio) as u32).max(1);
+            let fit_h = (((h - caption_h).saturating_sub(2 * margin) as f32 * options.fill_ratio)
+                as u32)
+                .max(1);
+            let small = dim.0 <= fit_w && dim.1 <= fit_h && dim != (fit_w, fit_h);
+            match options.small_image {
+                _ if options.no_resize => (w, h),
+                // Resampling to the same size would still filter the pixels.
+                _ if dim == (fit_w, fit_h) => (w, h),
+                _ if small && options.uniform_size => (w, h),
+                SmallImage::Skip if small => {
+                    return Err(FramerError::Skipped(format!(
+                        "{}x{} is smaller than {w}x{h}",
+                        dim.0, dim.1
+                    )));
+                }
+                SmallImage::Center if small => (w, h),
+                SmallImage::ShrinkCanvas if small => aspect_canvas(
+                    with_margin(fill_area(dim, options.fill_ratio), margin),
+                    w as f32,
+                    h as f32,
+                    caption,
+                ),
+                _ => {
+                    img = resize(&img, fit_w, fit_h, options);
+                    dim = img.dimensions();
+                    (w, h)
+                }
+            }
         }
         Sizing::AspectRatio(w, h) => {
-            if (dim.0 as f32 / w) < dim.1 as f32 / h {
-                // Border bars are vertical
-                RgbImage::from_pixel((dim.1 as f32 * (w / h)) as u32, dim.1, Rgb([255, 255, 255]))
+            if let Some(max) = options.max_aspect {
+                let (crop_w, crop_h) = max_aspect_crop(dim, max);
+                if (crop_w, crop_h) != dim {
+                    img = img.crop_imm((dim.0 - crop_w) / 2, (dim.1 - crop_h) / 2, crop_w, crop_h);
+                    dim = img.dimensions();
+                }
+            }
+            margin = photo_margin(dim, options);
+            let padded = |dim| with_margin(fill_area(dim, options.fill_ratio), margin);
+            let canvas_dim = aspect_canvas(padded(dim), w, h, caption);
+            let caption_h = caption.map_or(0, |caption| caption.resolve(canvas_dim.1));
+            // Aspect ratio of the area of the canvas the photo is placed in.
+            let area_ratio = w / h * canvas_dim.1 as f32 / (canvas_dim.1 - caption_h).max(1) as f32;
+            let photo_ratio = dim.0 as f32 / dim.1 as f32;
+            let mismatch = photo_ratio.max(area_ratio) / photo_ratio.min(area_ratio) - 1.0;
+            if mismatch > 0.0 && mismatch * 100.0 < options.snap_threshold {
+                let (crop_w, crop_h) = if photo_ratio > area_ratio {
+                    ((dim.1 as f32 * area_ratio).round() as u32, dim.1)
+                } else {
+                    (dim.0, (dim.0 as f32 / area_ratio).round() as u32)
+                };
+                img = img.crop_imm((dim.0 - crop_w) / 2, (dim.1 - crop_h) / 2, crop_w, crop_h);
+                dim = img.dimensions();
+                aspect_canvas(padded(dim), w, h, caption)
             } else {
-                // Border bars are horizontal
-                RgbImage::from_pixel(dim.0, (dim.0 as f32 * (h / w)) as u32, Rgb([255, 255, 255]))
+                canvas_dim
             }
         }
+        Sizing::Percent(percent) => {
+            let w = ((dim.0 as f32 * percent / 100.0).round() as u32).max(1);
+            let h = ((dim.1 as f32 * percent / 100.0).round() as u32).max(1);
+            if (w, h) != dim {
+                img = resize(&img, w, h, options);
+                dim = img.dimensions();
+            }
+            margin = photo_margin(dim, options);
+            let padded = with_margin(dim, margin);
+            (padded.0, captioned_height(padded.1, caption))
+        }
+    };
+    // Rounding, captions, margins, a brand bar and the like can leave an aspect ratio
+    // canvas short of the photo.
+    let (top, bottom) = reserved(canvas_dim.1, sizing, options);
+    let area = (
+        canvas_dim.0.saturating_sub(2 * margin),
+        canvas_dim
+            .1
+            .saturating_sub(top + bottom)
+            .saturating_sub(2 * margin),
+    );
+    let canvas_dim = match sizing {
+        Sizing::AspectRatio(..) if dim.0 > area.0 || dim.1 > area.1 => match options.overflow {
+            Overflow::Shrink => {
+                let scale = (area.0 as f32 / dim.0 as f32).min(area.1 as f32 / dim.1 as f32);
+                let w = ((dim.0 as f32 * scale) as u32).clamp(1, area.0.max(1));
+                let h = ((dim.1 as f32 * scale) as u32).clamp(1, area.1.max(1));
+                img = resize(&img, w, h, options);
+                dim = img.dimensions();
+                canvas_dim
+            }
+            Overflow::Expand => {
+                let percent = |length: Option<Length>| match length {
+                    Some(Length::Percent(percent)) => percent,
+                    _ => 0.0,
+                };
+                let bar = options.brand_bar.as_ref().map(|bar| bar.height);
+                // Space that grows with the canvas as fast as it does can't be grown past.
+                if percent(caption) + percent(bar) >= 100.0 {
+                    return Err(FramerError::Overflow(dim, area));
+                }
+                let needed = dim.1 + 2 * margin;
+                let mut height = canvas_dim.1.max(needed);
+                loop {
+                    let (top, bottom) = reserved(height, sizing, options);
+                    if height.saturating_sub(top + bottom) >= needed {
+                        break;
+                    }
+                    height = needed + top + bottom;
+                }
+                (canvas_dim.0.max(dim.0 + 2 * margin), height)
+            }
+            Overflow::Error => return Err(FramerError::Overflow(dim, area)),
+        },
+        _ => canvas_dim,
+    };
+    let canvas_dim = match options.pad_to_multiple {
+        Some(n) => (canvas_dim.0.div_ceil(n) * n, canvas_dim.1.div_ceil(n) * n),
+        None => canvas_dim,
+    };
+    let (top, bottom) = reserved(canvas_dim.1, sizing, options);
+    let offset_x = (canvas_dim.0 as i64 - dim.0 as i64) / 2;
+    let offset_y = top as i64 + (canvas_dim.1 as i64 - (top + bottom) as i64 - dim.1 as i64) / 2;
+    let solid_color = match backdrop {
+        Backdrop::Solid(color) => Some(color),
+        _ => None,
     };
-    let background_dim = background_image.dimensions();
-    // Widths are the same => Horizontal bars
-    if dim.0 == background_dim.0 {
-        let offset = (background_dim.1 - dim.1) / 2;
-        overlay(&mut background_image, &img.to_rgb8(), 0, offset as i64);
+    let mut background_image = if has_alpha && options.flatten_color.is_none() {
+        let mut photo = img.into_rgba32f();
+        let mut background_image = match solid_color {
+            Some(color) => RgbImage::from_pixel(canvas_dim.0, canvas_dim.1, color),
+            // A blurred background is built from the photo with its transparent parts
+            // left black.
+            None => backdrop.canvas(
+                canvas_dim.0,
+                canvas_dim.1,
+                &flatten(&photo, Rgb([0, 0, 0])),
+                options.background_filter,
+                (offset_x, offset_y),
+                false,
+            ),
+        };
+        if let Some(lut) = photo_lut {
+            lut.apply_premultiplied(&mut photo);
+        }
+        composite_premultiplied(&mut background_image, &photo, (offset_x, offset_y));
+        background_image
     } else {
-        // Heights are the same => Vertical bars
-        let offset = (background_dim.0 - dim.0) / 2;
-        overlay(&mut background_image, &img.to_rgb8(), offset as i64, 0);
+        let mut photo = match options.flatten_color {
+            Some(color) if has_alpha => flatten(&img.into_rgba32f(), color),
+            _ => img.into_rgb8(),
+        };
+        match solid_color {
+            Some(color) => {
+                if let Some(lut) = photo_lut {
+                    lut.apply_rgb8(&mut photo);
+                }
+                pad_solid(&photo, canvas_dim, (offset_x, offset_y), color)
+            }
+            None => {
+                let mut background_image = backdrop.canvas(
+                    canvas_dim.0,
+                    canvas_dim.1,
+                    &photo,
+                    options.background_filter,
+                    (offset_x, offset_y),
+                    true,
+                );
+                if let Some(lut) = photo_lut {
+                    lut.apply_rgb8(&mut photo);
+                }
+                overlay(&mut background_image, &photo, offset_x, offset_y);
+                background_image
+            }
+        }
+    };
+    if let Some(bar) = &options.brand_bar {
+        draw_brand_bar(&mut background_image, bar, options);
+    }
+    if let Some((width, color)) = options.edge_stroke {
+        draw_edge_stroke(&mut background_image, width, color);
+    }
+    Ok(FrameResult {
+        image: background_image,
+        width: canvas_dim.0,
+        height: canvas_dim.1,
+        ratio: canvas_dim.0 as f64 / canvas_dim.1 as f64,
+        photo: (offset_x, offset_y, dim.0, dim.1),
+    })
+}
+
+/// Returns the heights at the top and bottom of a canvas `height` pixels tall that the
+/// photo is centered between: the caption space, and in [`Sizing::AspectRatio`] mode a
+/// brand bar, which the photo is otherwise drawn under.
+fn reserved(height: u32, sizing: Sizing, options: &FramerOptions) -> (u32, u32) {
+    let caption = options
+        .caption_space
+        .map_or(0, |caption| caption.resolve(height));
+    match &options.brand_bar {
+        Some(bar) if matches!(sizing, Sizing::AspectRatio(..)) => {
+            let bar_h = bar.height.resolve(height).min(height);
+            match bar.position {
+                BarPosition::Top => (bar_h, caption),
+                BarPosition::Bottom => (0, caption.max(bar_h)),
+            }
+        }
+        _ => (0, caption),
+    }
+}
+
+/// Returns whether both paths refer to the same existing file or directory.
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Returns the directory containing `file`, treating bare file names as being in
+/// the current directory.
+pub fn parent_dir(file: &Path) -> &Path {
+    match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Returns the format an output is written in, which is always the one of the extension
+/// of its path. Failing if no encoder is available for it, this checks outputs before
+/// any image is framed.
+pub fn output_format(output: &Path) -> Result<ImageFormat, FramerError> {
+    let extension = output
+        .extension()
+        .map_or(String::new(), |ext| ext.to_string_lossy().into_owned());
+    ImageFormat::from_extension(&extension)
+        .filter(|format| format.can_write() && format.writing_enabled())
+        .ok_or(FramerError::UnsupportedOutput(extension))
+}
+
+/// Builds the path a framed copy of `file` is written to inside `output_dir`. Its
+/// extension, and so the output format, is [`FramerOptions::output_extension`] if set,
+/// and otherwise the one of `file`.
+pub fn output_path(output_dir: &Path, file: &Path, options: &FramerOptions) -> Option<PathBuf> {
+    let mut filename = OsString::from(file.file_stem()?);
+    if let Some(suffix) = &options.suffix {
+        filename.push(suffix);
+    }
+    let extension = match &options.output_extension {
+        Some(extension) => Some(OsString::from(extension)),
+        // RAW images can't be encoded, so they are framed into JPEG.
+        #[cfg(feature = "raw")]
+        None if raw::is_raw(file) => Some(OsString::from("jpg")),
+        None => file.extension().map(|ext| ext.to_os_string()),
+    };
+    if let Some(extension) = extension {
+        filename.push(".");
+        filename.push(extension);
+    }
+    Some(output_dir.join(filename))
+}
+
+/// Sorts `files` by `key`, in descending order if `descending` is set. Files whose
+/// metadata can't be read sort as the oldest and smallest.
+fn sort_files(files: &mut [PathBuf], key: SortKey, descending: bool) {
+    match key {
+        SortKey::Name => files.sort(),
+        SortKey::Mtime => files.sort_by_cached_key(|file| {
+            fs::metadata(file)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }),
+        SortKey::Size => {
+            files.sort_by_cached_key(|file| fs::metadata(file).map_or(0, |metadata| metadata.len()))
+        }
+    }
+    if descending {
+        files.reverse();
+    }
+}
+
+/// Returns whether `file` is framed in batch mode. Files without an extension are
+/// attempted, as the decoder will reject them if they aren't images.
+fn is_candidate(file: &Path, options: &FramerOptions) -> bool {
+    !file.is_dir()
+        && file.file_name().is_none_or(|name| name != LOCK_FILE)
+        && (!options.extension_filter || file.extension().is_none() || has_accepted_extension(file))
+}
+
+/// Checks that this build can decode `file`, failing with [`FramerError::MissingFeature`]
+/// for camera RAW files without the `raw` feature.
+pub fn check_support(file: &Path) -> Result<(), FramerError> {
+    if !cfg!(feature = "raw") && raw::is_raw(file) {
+        return Err(FramerError::MissingFeature("camera RAW", "raw"));
     }
-    background_image.save(output)?;
     Ok(())
 }
+
+/// Returns whether `file` has one of the [`ACCEPTED_EXTENSIONS`], or that of a camera
+/// RAW file when built with the `raw` feature.
+pub fn has_accepted_extension(file: &Path) -> bool {
+    #[cfg(feature = "raw")]
+    if raw::is_raw(file) {
+        return true;
+    }
+    file.extension()
+        .is_some_and(|ext| ACCEPTED_EXTENSIONS.contains(&ext.display().to_string().as_str()))
+}
+
+/// Reads the dimensions of `file` from its header, as they are after the rotation
+/// [`decode`] applies.
+fn oriented_dimensions(file: &PathBuf, options: &FramerOptions) -> Result<(u32, u32), ImageError> {
+    // RAW headers can't be read without the converter, so the image is developed.
+    #[cfg(feature = "raw")]
+    if raw::is_raw(file) {
+        return Ok(raw::decode(file)?.dimensions());
+    }
+    let mut decoder = ImageReader::open(file)?
+        .with_guessed_format()?
+        .into_decoder()?;
+    let dim = decoder.dimensions();
+    let exif = match options.orientation {
+        OrientationHandling::Ignore => None,
+        _ => decoder.exif_metadata()?.map(|data| exif::parse(&data)),
+    };
+    Ok(match exif {
+        Some(exif)
+            if orientation_problem(&exif, dim).is_none()
+                && exif
+                    .orientation
+                    .is_some_and(|value| (5..=8).contains(&value)) =>
+        {
+            (dim.1, dim.0)
+        }
+        _ => dim,
+    })
+}
+
+/// Returns the largest of the canvases `files` would be framed into with `sizing`,
+/// cropped to [`FramerOptions::max_aspect`] and with their margin and caption space.
+/// Files whose dimensions can't be read are left out, and fail when framed.
+fn uniform_canvas(files: &[PathBuf], sizing: Sizing, options: &FramerOptions) -> (u32, u32) {
+    let Sizing::AspectRatio(w, h) = sizing else {
+        unreachable!("only aspect ratio canvases depend on the input");
+    };
+    files
+        .iter()
+        .filter_map(|file| oriented_dimensions(file, options).ok())
+        // Photos the crop doesn't fit in fail, so all others are framed at its size.
+        .map(|dim| options.crop.map_or(dim, |(_, _, w, h)| (w, h)))
+        .map(|dim| {
+            options
+                .max_aspect
+                .map_or(dim, |max| max_aspect_crop(dim, max))
+        })
+        .map(|dim| {
+            aspect_canvas(
+                with_margin(
+                    fill_area(dim, options.fill_ratio),
+                    photo_margin(dim, options),
+                ),
+                w,
+                h,
+                options.caption_space,
+            )
+        })
+        .max_by_key(|(w, h)| *w as u64 * *h as u64)
+        .unwrap_or((0, 0))
+}
+
+/// Perceptual difference hash of `img`: one bit per pair of horizontally neighboring
+/// pixels of a 9x8 grayscale thumbnail, set where the left pixel is brighter. Similar
+/// images have hashes differing in few bits.
+fn dhash(img: &DynamicImage) -> u64 {
+    let thumbnail = img.resize_exact(9, 8, FilterType::Triangle).into_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = thumbnail.get_pixel(x, y)[0] > thumbnail.get_pixel(x + 1, y)[0];
+            hash = hash << 1 | brighter as u64;
+        }
+    }
+    hash
+}
+
+/// Total size of the decoded images a [`DecodeCache`] holds at most.
+const DECODE_CACHE_BYTES: usize = 512 << 20;
+
+/// Decoded images of a batch that are framed again later in it, whether by
+/// [`find_duplicates`] or by the earlier job of an image framed into several outputs, so
+/// each is only decoded once. Images that would take the cache past
+/// [`DECODE_CACHE_BYTES`] aren't kept, and each image is dropped once its last job has
+/// taken it. Jobs of an image framed at the same time may still each decode it.
+#[derive(Default)]
+struct DecodeCache {
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    images: HashMap<PathBuf, DynamicImage>,
+    /// Jobs not yet framed of each file, for those with any.
+    uses: HashMap<PathBuf, usize>,
+    bytes: usize,
+}
+
+impl DecodeCache {
+    /// A cache for a batch framing each of `files` once per time it appears.
+    fn new<'a>(files: impl IntoIterator<Item = &'a PathBuf>) -> DecodeCache {
+        let mut uses = HashMap::new();
+        for file in files {
+            *uses.entry(file.clone()).or_insert(0) += 1;
+        }
+        DecodeCache {
+            state: Mutex::new(CacheState {
+                uses,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Keeps a copy of `img`, decoded from `file`, if a job of `file` is left to take it.
+    fn insert(&self, file: &Path, img: &DynamicImage) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let size = img.as_bytes().len();
+        if state.uses.get(file).is_some_and(|&uses| uses > 0)
+            && !state.images.contains_key(file)
+            && state.bytes + size <= DECODE_CACHE_BYTES
+        {
+            state.bytes += size;
+            state.images.insert(file.to_path_buf(), img.clone());
+        }
+    }
+
+    /// Counts off a job of `file`, returning its decoded image if it's kept: a copy while
+    /// other jobs of `file` are left, and the image itself for the last one.
+    fn take(&self, file: &Path) -> Option<DynamicImage> {
+        let mut state = self.state.lock().ok()?;
+        let uses = state.uses.get_mut(file).map_or(0, |uses| {
+            *uses = uses.saturating_sub(1);
+            *uses
+        });
+        if uses > 0 {
+            return state.images.get(file).cloned();
+        }
+        state.uses.remove(file);
+        let img = state.images.remove(file)?;
+        state.bytes -= img.as_bytes().len();
+        Some(img)
+    }
+}
+
+/// For each of `files`, the first earlier file it looks like, if any. Every file is
+/// decoded for this, in parallel, before the batch is framed, so duplicates are found
+/// in input order regardless of which image finishes framing first.
+fn find_duplicates(
+    files: &[PathBuf],
+    options: &FramerOptions,
+    cache: &DecodeCache,
+) -> Vec<Option<PathBuf>> {
+    let chunk = files.len().div_ceil(options.jobs.max(1)).max(1);
+    let hashes: Vec<Option<u64>> = thread::scope(|scope| {
+        let workers: Vec<_> = files
+            .chunks(chunk)
+            .map(|files| {
+                scope.spawn(move || {
+                    files
+                        .iter()
+                        .map(|file| {
+                            let img = decode(file, options).ok()?;
+                            cache.insert(file, &img);
+                            Some(dhash(&img))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect()
+    });
+    let mut seen: Vec<(u64, &PathBuf)> = Vec::new();
+    files
+        .iter()
+        .zip(hashes)
+        .map(|(file, hash)| {
+            let hash = hash?;
+            let original = seen
+                .iter()
+                .find(|(seen, _)| (seen ^ hash).count_ones() <= options.dedup_threshold)
+                .map(|(_, original)| (*original).clone());
+            if original.is_none() {
+                seen.push((hash, file));
+            }
+            original
+        })
+        .collect()
+}
+
+/// Outcome of framing one file of a batch: its output path and details, or the error.
+pub type FileResult = Result<(PathBuf, FrameDetails), FramerError>;
+
+/// Tallies of the outcomes of a batch.
+#[derive(Default)]
+pub struct BatchCounts {
+    pub attempted: usize,
+    pub framed: usize,
+    /// Images deliberately left unframed, including skipped duplicates.
+    pub skipped: usize,
+    /// Images that failed to frame.
+    pub failed: usize,
+    /// Images smaller than [`FramerOptions::min_input_size`], framed or not.
+    pub undersized: usize,
+    /// Images that look like an earlier image, framed or not.
+    pub duplicates: usize,
+}
+
+impl BatchCounts {
+    pub fn of(results: &[(PathBuf, FileResult)]) -> BatchCounts {
+        let mut counts = BatchCounts::default();
+        for (_, result) in results {
+            counts.record(result);
+        }
+        counts
+    }
+
+    fn record(&mut self, result: &FileResult) {
+        self.attempted += 1;
+        match result {
+            Ok(_) => self.framed += 1,
+            Err(FramerError::Skipped(_) | FramerError::Duplicate(_)) => self.skipped += 1,
+            Err(_) => self.failed += 1,
+        }
+        self.undersized += match result {
+            Ok((_, details)) => details.undersized,
+            Err(e) => matches!(e, FramerError::Undersized(..)),
+        } as usize;
+        self.duplicates += match result {
+            Ok((_, details)) => details.duplicate_of.is_some(),
+            Err(e) => matches!(e, FramerError::Duplicate(_)),
+        } as usize;
+    }
+}
+
+/// For each of `files`, the directories between the files' common ancestor and the
+/// file, joined by `_`, so that files of different directories get distinct names in
+/// one output directory. For example `2023/jan/img.jpg` gets the prefix `2023_jan_`.
+fn flattened_prefixes(files: &[PathBuf]) -> Vec<String> {
+    let parents: Vec<Vec<OsString>> = files
+        .iter()
+        .map(|file| {
+            std::path::absolute(parent_dir(file))
+                .unwrap_or_else(|_| parent_dir(file).to_path_buf())
+                .components()
+                .map(|component| component.as_os_str().to_os_string())
+                .collect()
+        })
+        .collect();
+    let common = parents.first().map_or(0, |first| {
+        parents.iter().fold(first.len(), |common, parent| {
+            first
+                .iter()
+                .zip(parent)
+                .take(common)
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+    });
+    parents
+        .iter()
+        .map(|parent| {
+            parent[common..]
+                .iter()
+                .map(|component| format!("{}_", component.to_string_lossy()))
+                .collect()
+        })
+        .collect()
+}
+
+/// Builds the output path of each of `files`, failing those whose output would
+/// overwrite that of an earlier file.
+fn plan_outputs(
+    files: &[PathBuf],
+    output: Option<&Path>,
+    options: &FramerOptions,
+) -> Vec<Result<PathBuf, FramerError>> {
+    let prefixes = match output {
+        Some(_) if options.flatten_output => flattened_prefixes(files),
+        _ => vec![String::new(); files.len()],
+    };
+    let mut planned = HashMap::new();
+    files
+        .iter()
+        .zip(prefixes)
+        .map(|(file, prefix)| {
+            let output_dir = output.unwrap_or(parent_dir(file));
+            let mut output =
+                output_path(output_dir, file, options).ok_or(FramerError::NoFileName)?;
+            if !prefix.is_empty() {
+                let mut filename = OsString::from(prefix);
+                filename.push(output.file_name().ok_or(FramerError::NoFileName)?);
+                output.set_file_name(filename);
+            }
+            output_format(&output)?;
+            if let Some(other) = planned.insert(output.clone(), file) {
+                planned.insert(output, other);
+                return Err(FramerError::OutputCollision(other.clone()));
+            }
+            Ok(output)
+        })
+        .collect()
+}
+
+fn frame_file(
+    file: &PathBuf,
+    output: Result<PathBuf, FramerError>,
+    sizing: Sizing,
+    options: &FramerOptions,
+    cache: Option<&DecodeCache>,
+) -> FileResult {
+    let output = output?;
+    if same_path(&output, file) {
+        return Err(FramerError::OverwritesSource);
+    }
+    let details = frame_image_cached(file, &output, sizing, options, cache)?;
+    Ok((output, details))
+}
+
+/// Frames one file of a batch, returning the result along with the messages logged
+/// meanwhile, so they can be flushed in input order.
+fn frame_logged(
+    file: &PathBuf,
+    output: Result<PathBuf, FramerError>,
+    sizing: Sizing,
+    options: &FramerOptions,
+    cache: Option<&DecodeCache>,
+) -> (FileResult, Vec<logging::Message>) {
+    logging::capture(|| {
+        let result = frame_file(file, output, sizing, options, cache);
+        match &result {
+            Ok(_) => {}
+            Err(FramerError::Skipped(reason)) => {
+                warn!("Skipped {}: {reason}", file.display())
+            }
+            Err(e @ FramerError::Duplicate(_)) => warn!("Skipped {}: {e}", file.display()),
+            Err(e) => error!("Failed to frame image {}: {e}", file.display()),
+        }
+        result
+    })
+}
+
+/// Frames every accepted image in `files`, writing into `output`, or next to each
+/// image if `output` is `None`. Up to [`FramerOptions::jobs`] images are framed in
+/// parallel, and the messages logged for each image are flushed together, in input
+/// order. Images whose output path is that of an earlier image fail instead of
+/// overwriting it. Returns the output path and details, or the error, of each image
+/// attempted.
+pub fn frame_files(
+    files: Vec<PathBuf>,
+    output: Option<&Path>,
+    sizing: Sizing,
+    options: &FramerOptions,
+) -> Vec<(PathBuf, FileResult)> {
+    let (files, outputs) = plan_files(files, output, options);
+    let sizings = vec![None; files.len()];
+    frame_planned(files, outputs, sizings, sizing, options)
+}
+
+/// Picks the accepted images of `files`, in the order they are framed, and plans the
+/// output path of each as [`frame_files`] would.
+pub fn plan_files(
+    files: Vec<PathBuf>,
+    output: Option<&Path>,
+    options: &FramerOptions,
+) -> (Vec<PathBuf>, Vec<Result<PathBuf, FramerError>>) {
+    let unsupported: Vec<FramerError> = files
+        .iter()
+        .filter_map(|file| check_support(file).err())
+        .collect();
+    if let Some(e) = unsupported.first() {
+        warn!("Skipping {} images: {e}.", unsupported.len());
+    }
+    let mut files: Vec<PathBuf> = files
+        .into_iter()
+        .filter(|file| is_candidate(file, options) && check_support(file).is_ok())
+        .collect();
+    let sort = options
+        .sort
+        .or(options.sort_descending.then_some(SortKey::Name));
+    if let Some(key) = sort {
+        sort_files(&mut files, key, options.sort_descending);
+    }
+    let outputs = plan_outputs(&files, output, options);
+    (files, outputs)
+}
+
+/// Frames each of `files` into its planned output, as made by [`plan_files`], at its
+/// entry of `sizings` or at `sizing` without one. Images without an output fail with
+/// the error planned for them. Images appearing several times, such as in a jobs file
+/// framing one image at several sizings, are decoded once while memory allows.
+pub fn frame_planned(
+    files: Vec<PathBuf>,
+    mut outputs: Vec<Result<PathBuf, FramerError>>,
+    sizings: Vec<Option<Sizing>>,
+    sizing: Sizing,
+    options: &FramerOptions,
+) -> Vec<(PathBuf, FileResult)> {
+    let sizing = match sizing {
+        Sizing::AspectRatio(..) if options.uniform_size => {
+            let (w, h) = uniform_canvas(&files, sizing, options);
+            info!("Framing every image into a uniform {w}x{h} canvas.");
+            Sizing::Dimensions(w, h)
+        }
+        sizing => sizing,
+    };
+    let cache = DecodeCache::new(
+        files
+            .iter()
+            .zip(&outputs)
+            .filter(|(_, output)| output.is_ok())
+            .map(|(file, _)| file),
+    );
+    let duplicates = match options.dedup {
+        Some(_) => find_duplicates(&files, options, &cache),
+        None => vec![None; files.len()],
+    };
+    for ((file, output), original) in files.iter().zip(&mut outputs).zip(&duplicates) {
+        match (original, options.dedup) {
+            (Some(original), Some(DedupMode::Skip)) if output.is_ok() => {
+                *output = Err(FramerError::Duplicate(original.clone()));
+            }
+            (Some(original), Some(DedupMode::Flag)) => warn!(
+                "{} looks like a near duplicate of {}.",
+                file.display(),
+                original.display()
+            ),
+            _ => {}
+        }
+        // Images that won't be framed don't need to stay decoded.
+        if matches!(output, Err(FramerError::Duplicate(_))) {
+            cache.take(file);
+        }
+    }
+    let queue = Mutex::new(files.iter().zip(outputs).zip(sizings).enumerate());
+    let (sender, receiver) = mpsc::channel();
+    let mut results = Vec::with_capacity(files.len());
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            let sender = sender.clone();
+            let queue = &queue;
+            let cache = &cache;
+            scope.spawn(move || {
+                loop {
+                    let next = queue.lock().map(|mut queue| queue.next());
+                    let Ok(Some((index, ((file, output), job_sizing)))) = next else {
+                        break;
+                    };
+                    let sizing = job_sizing.unwrap_or(sizing);
+                    let (result, messages) =
+                        frame_logged(file, output, sizing, options, Some(cache));
+                    if sender.send((index, result, messages)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        let mut pending = BTreeMap::new();
+        for (index, result, messages) in receiver {
+            pending.insert(index, (result, messages));
+            while let Some((mut result, messages)) = pending.remove(&results.len()) {
+                logging::flush(messages);
+                if let Ok((_, details)) = &mut result {
+                    details.duplicate_of = duplicates[results.len()].clone();
+                }
+                results.push((files[results.len()].clone(), result));
+            }
+        }
+    });
+    results
+}
+
+/// Frames every accepted image directly inside the `input` directory into `output`.
+pub fn frame_directory(
+    input: &Path,
+    output: &Path,
+    sizing: Sizing,
+    options: &FramerOptions,
+) -> Vec<(PathBuf, FileResult)> {
+    match directory_files(input) {
+        Ok(files) => frame_files(files, Some(output), sizing, options),
+        Err(e) => vec![(input.to_path_buf(), Err(e))],
+    }
+}
+
+/// Lists the entries directly inside the `input` directory, in name order.
+pub fn directory_files(input: &Path) -> Result<Vec<PathBuf>, FramerError> {
+    let mut files: Vec<PathBuf> = input
+        .read_dir()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    // `read_dir` yields entries in no particular order.
+    files.sort();
+    Ok(files)
+}
+
+/// Images [`stream_directory`] reads ahead of the earliest one still being framed, per job.
+const STREAM_WINDOW: usize = 4;
+
+/// Frames every accepted image directly inside the `input` directory into `output` as
+/// `read_dir` yields them, without first collecting, sorting or planning the whole
+/// directory. Images are framed in the order they are read, up to
+/// [`FramerOptions::jobs`] at a time, and at most [`STREAM_WINDOW`] times as many paths
+/// are held at once, however long an earlier image takes. Output collisions are still
+/// detected when [`FramerOptions::output_extension`] makes them possible, at the cost of
+/// remembering each output path. So are the names of outputs written into `input`
+/// itself, which `read_dir` may yield too, so that they aren't framed again.
+pub fn stream_directory(
+    input: &Path,
+    output: &Path,
+    sizing: Sizing,
+    options: &FramerOptions,
+) -> BatchCounts {
+    let mut counts = BatchCounts::default();
+    let entries = match input.read_dir() {
+        Ok(entries) => entries,
+        Err(e) => {
+            counts.record(&Err(e.into()));
+            return counts;
+        }
+    };
+    let jobs = options.jobs.max(1);
+    let (file_sender, file_receiver) = mpsc::sync_channel(jobs);
+    let file_receiver = Mutex::new(file_receiver);
+    let (result_sender, result_receiver) = mpsc::sync_channel(jobs);
+    // One message per recorded result, letting the reader past the window.
+    let (recorded_sender, recorded_receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            let mut planned = HashMap::new();
+            let in_place = same_path(input, output);
+            let mut written = HashSet::new();
+            let files = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|file| is_candidate(file, options));
+            let mut index = 0;
+            for file in files {
+                if file.file_name().is_some_and(|name| written.contains(name)) {
+                    continue;
+                }
+                if index >= STREAM_WINDOW * jobs && recorded_receiver.recv().is_err() {
+                    break;
+                }
+                let path = output_path(output, &file, options)
+                    .ok_or(FramerError::NoFileName)
+                    .and_then(|path| output_format(&path).map(|_| path));
+                let path = match path {
+                    Ok(path) if options.output_extension.is_some() => {
+                        match planned.insert(path.clone(), file.clone()) {
+                            Some(other) => {
+                                planned.insert(path, other.clone());
+                                Err(FramerError::OutputCollision(other))
+                            }
+                            None => Ok(path),
+                        }
+                    }
+                    path => path,
+                };
+                if in_place && let Ok(path) = &path {
+                    written.extend(path.file_name().map(OsString::from));
+                    if options.comparison {
+                        written.extend(comparison_path(path).file_name().map(OsString::from));
+                    }
+                }
+                if file_sender.send((index, file, path)).is_err() {
+                    break;
+                }
+                index += 1;
+            }
+        });
+        for _ in 0..jobs {
+            let result_sender = result_sender.clone();
+            let file_receiver = &file_receiver;
+            scope.spawn(move || {
+                loop {
+                    let next = file_receiver.lock().map(|receiver| receiver.recv());
+                    let Ok(Ok((index, file, path))) = next else {
+                        break;
+                    };
+                    let (result, messages) = frame_logged(&file, path, sizing, options, None);
+                    if result_sender.send((index, result, messages)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+        let mut pending = BTreeMap::new();
+        for (index, result, messages) in result_receiver {
+            pending.insert(index, (result, messages));
+            while let Some((result, messages)) = pending.remove(&counts.attempted) {
+                logging::flush(messages);
+                counts.record(&result);
+                let _ = recorded_sender.send(());
+            }
+        }
+    });
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for the files of the test `name`.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("photo_framer_{}_{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes a `w`x`h` image of `color` to `dir`.
+    fn solid_png(dir: &Path, name: &str, (w, h): (u32, u32), color: [u8; 3]) -> PathBuf {
+        let path = dir.join(name);
+        RgbImage::from_pixel(w, h, Rgb(color)).save(&path).unwrap();
+        path
+    }
+
+    /// A `w`x`h` image of deterministic noise.
+    fn noise((w, h): (u32, u32)) -> RgbImage {
+        RgbImage::from_fn(w, h, |x, y| {
+            let n = (x.wrapping_mul(7919) ^ y.wrapping_mul(104729)).wrapping_mul(2654435761);
+            Rgb([(n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8])
+        })
+    }
+
+    #[test]
+    fn pad_solid_matches_overlaying_onto_a_filled_canvas() {
+        let photo = noise((30, 20));
+        let color = Rgb([12, 34, 56]);
+        // Centered, against each edge, and clipped on every side.
+        for offset in [(5, 10), (0, 0), (10, 20), (-4, -3), (8, 15), (-10, -10)] {
+            let mut expected = RgbImage::from_pixel(40, 40, color);
+            overlay(&mut expected, &photo, offset.0, offset.1);
+            assert!(
+                pad_solid(&photo, (40, 40), offset, color) == expected,
+                "mismatch at offset {offset:?}"
+            );
+        }
+        let large = noise((50, 20));
+        let mut expected = RgbImage::from_pixel(40, 40, color);
+        overlay(&mut expected, &large, -5, 10);
+        assert!(pad_solid(&large, (40, 40), (-5, 10), color) == expected);
+    }
+
+    /// An opaque red square with a half transparent edge, on transparent black.
+    fn red_on_transparent_black() -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(40, 40, |x, y| {
+            match (x.abs_diff(20).max(y.abs_diff(20)), x.min(y)) {
+                (0..10, _) => Rgba([255, 0, 0, 255]),
+                (10..12, _) => Rgba([255, 0, 0, 128]),
+                _ => Rgba([0, 0, 0, 0]),
+            }
+        }))
+    }
+
+    #[test]
+    fn transparent_edges_blend_with_the_matte() {
+        let framed = frame_image_buffer(
+            red_on_transparent_black(),
+            Sizing::Percent(100.0),
+            &FramerOptions::default(),
+        )
+        .unwrap();
+        // Half of white shows through the edge, and all of it around the square.
+        let edge = framed.image.get_pixel(9, 20).0;
+        assert_eq!(edge[0], 255);
+        assert!(
+            edge[1].abs_diff(127) <= 1 && edge[2].abs_diff(127) <= 1,
+            "{edge:?}"
+        );
+        assert_eq!(framed.image.get_pixel(2, 2), &Rgb([255, 255, 255]));
+        assert_eq!(framed.image.get_pixel(20, 20), &Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn downscaled_transparent_edges_have_no_dark_halo() {
+        let framed = frame_image_buffer(
+            red_on_transparent_black(),
+            Sizing::Dimensions(15, 15),
+            &FramerOptions::default(),
+        )
+        .unwrap();
+        // Only red and white are mixed, so red stays full and green and blue equal.
+        for pixel in framed.image.pixels() {
+            let [r, g, b] = pixel.0;
+            assert!(r >= 254 && g.abs_diff(b) <= 1, "{:?}", pixel.0);
+        }
+    }
+
+    #[test]
+    fn comparison_shows_transparency_on_the_border_color() {
+        let options = FramerOptions {
+            background: Background::Solid(Rgb([0, 0, 255])),
+            ..Default::default()
+        };
+        let original = red_on_transparent_black();
+        let framed = RgbImage::from_pixel(60, 40, Rgb([0, 0, 255]));
+        let comparison = compose_comparison(&original, &framed, &options);
+        assert_eq!(comparison.dimensions(), (40 + 2 + 60, 40));
+        assert_eq!(comparison.get_pixel(2, 2), &Rgb([0, 0, 255]));
+        assert_eq!(comparison.get_pixel(20, 20), &Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn flatten_color_fills_transparency_of_a_jpeg_output() {
+        let dir = test_dir("flatten_color");
+        let input = dir.join("in.png");
+        red_on_transparent_black().save(&input).unwrap();
+        let options = FramerOptions {
+            flatten_color: Some(Rgb([0, 255, 0])),
+            ..Default::default()
+        };
+        let output = dir.join("out.jpg");
+        frame_image(&input, &output, Sizing::Percent(100.0), &options).unwrap();
+        let framed = image::open(&output).unwrap().into_rgb8();
+        for (x, y) in [(2, 2), (37, 2), (2, 37), (37, 37)] {
+            let [r, g, b] = framed.get_pixel(x, y).0;
+            assert!(r <= 4 && g >= 251 && b <= 4, "{:?} at {x},{y}", [r, g, b]);
+        }
+        // The half transparent edge is an even blend, exactly so without compression.
+        let output = dir.join("out.png");
+        frame_image(&input, &output, Sizing::Percent(100.0), &options).unwrap();
+        let framed = image::open(&output).unwrap().into_rgb8();
+        let [r, g, b] = framed.get_pixel(9, 20).0;
+        assert!(
+            r.abs_diff(128) <= 1 && g.abs_diff(127) <= 1 && b == 0,
+            "{:?}",
+            [r, g, b]
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn photo_at_the_target_size_is_not_resampled() {
+        let photo = noise((50, 40));
+        for sizing in [Sizing::Dimensions(50, 40), Sizing::Percent(100.0)] {
+            let img = DynamicImage::ImageRgb8(photo.clone());
+            let framed = frame_image_buffer(img, sizing, &FramerOptions::default()).unwrap();
+            assert!(framed.image == photo);
+        }
+    }
+
+    #[test]
+    fn uniform_canvas_includes_margin_and_max_aspect() {
+        let dir = test_dir("uniform_canvas");
+        let files = vec![
+            solid_png(&dir, "a.png", (60, 40), [255, 0, 0]),
+            solid_png(&dir, "b.png", (40, 30), [0, 0, 255]),
+            solid_png(&dir, "c.png", (90, 30), [0, 255, 0]),
+        ];
+        let options = FramerOptions {
+            margin: Some(Margin {
+                pixels: 10,
+                percent: 0.0,
+            }),
+            max_aspect: Some(2.0),
+            uniform_size: true,
+            ..Default::default()
+        };
+        // The 90x30 photo is cropped to 60x30, leaving 60x40 the largest.
+        assert_eq!(
+            uniform_canvas(&files, Sizing::AspectRatio(1.0, 1.0), &options),
+            (80, 80)
+        );
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        for (_, result) in frame_files(files, Some(&out), Sizing::AspectRatio(1.0, 1.0), &options) {
+            assert_eq!(result.unwrap().1.framed, (80, 80));
+        }
+        // Photos keep their native size inside the margin.
+        let framed = image::open(out.join("a.png")).unwrap().into_rgb8();
+        assert_eq!(framed.get_pixel(10, 20), &Rgb([255, 0, 0]));
+        assert_eq!(framed.get_pixel(69, 59), &Rgb([255, 0, 0]));
+        assert_eq!(framed.get_pixel(70, 59), &Rgb([255, 255, 255]));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn embedded_thumbnail_keeps_normal_orientation() {
+        let dir = test_dir("embedded_thumbnail");
+        let input = solid_png(&dir, "in.png", (300, 200), [0, 0, 255]);
+        let output = dir.join("out.jpg");
+        let options = FramerOptions {
+            embed_thumbnail: Some(60),
+            ..Default::default()
+        };
+        frame_image(&input, &output, Sizing::AspectRatio(1.0, 1.0), &options).unwrap();
+        let mut decoder = ImageReader::open(&output).unwrap().into_decoder().unwrap();
+        let data = decoder.exif_metadata().unwrap().unwrap();
+        assert_eq!(exif::parse(&data).orientation, Some(1));
+        // IFD1 of the block written by `insert_jpeg_thumbnail` points at the thumbnail.
+        let offset = u32::from_be_bytes(data[48..52].try_into().unwrap()) as usize;
+        let length = u32::from_be_bytes(data[60..64].try_into().unwrap()) as usize;
+        let thumbnail = image::load_from_memory(&data[offset..offset + length]).unwrap();
+        assert_eq!(thumbnail.dimensions(), (60, 60));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stream_directory_frames_more_images_than_its_window() {
+        let dir = test_dir("stream_directory");
+        let input = dir.join("in");
+        let output = dir.join("out");
+        fs::create_dir_all(&input).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        let count = STREAM_WINDOW * 2 * 3;
+        for i in 0..count {
+            solid_png(&input, &format!("{i}.png"), (8, 6), [0, 0, 0]);
+        }
+        let options = FramerOptions {
+            jobs: 2,
+            ..Default::default()
+        };
+        let counts = stream_directory(&input, &output, Sizing::AspectRatio(1.0, 1.0), &options);
+        assert_eq!((counts.attempted, counts.framed), (count, count));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stream_directory_in_place_skips_its_own_outputs() {
+        let dir = test_dir("stream_in_place");
+        // Enough entries that `read_dir` lists the directory in several reads, after the
+        // first outputs are written.
+        let count = 1500;
+        for i in 0..count {
+            solid_png(&dir, &format!("{i}.png"), (8, 6), [0, 0, 0]);
+        }
+        let options = FramerOptions {
+            jobs: 2,
+            suffix: Some(String::from("_framed")),
+            comparison: true,
+            ..Default::default()
+        };
+        let counts = stream_directory(&dir, &dir, Sizing::AspectRatio(1.0, 1.0), &options);
+        assert_eq!((counts.attempted, counts.framed), (count, count));
+        // Each input, its output and the output's comparison.
+        assert_eq!(directory_files(&dir).unwrap().len(), count * 3);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn min_dimension_enlarges_small_framed_images() {
+        let dir = test_dir("min_dimension");
+        let input = solid_png(&dir, "in.png", (30, 20), [255, 0, 0]);
+        let output = dir.join("out.png");
+        let options = FramerOptions {
+            min_dimension: Some(100),
+            ..Default::default()
+        };
+        for (sizing, enlarged) in [
+            (Sizing::AspectRatio(3.0, 2.0), (150, 100)),
+            (Sizing::AspectRatio(4.0, 5.0), (100, 123)),
+            (Sizing::Dimensions(200, 150), (200, 150)),
+        ] {
+            let details = frame_image(&input, &output, sizing, &options).unwrap();
+            assert_eq!(details.framed, enlarged);
+            assert_eq!(image::image_dimensions(&output).unwrap(), enlarged);
+            assert!(enlarged.0.min(enlarged.1) >= 100);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Writes a 40x20 JPEG, red on the left and blue on the right, tagged with the EXIF
+    /// `orientation`.
+    fn oriented_jpeg(dir: &Path, orientation: u16) -> PathBuf {
+        let img = RgbImage::from_fn(40, 20, |x, _| {
+            if x < 20 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        let mut encoded = Cursor::new(Vec::new());
+        img.write_to(&mut encoded, ImageFormat::Jpeg).unwrap();
+        let encoded = encoded.into_inner();
+        // A big endian TIFF block with an IFD0 of just the orientation.
+        let mut app1 = b"Exif\0\0MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01".to_vec();
+        app1.extend(orientation.to_be_bytes());
+        app1.extend([0; 6]);
+        let mut tagged = encoded[..2].to_vec();
+        tagged.extend([0xFF, 0xE1]);
+        tagged.extend((app1.len() as u16 + 2).to_be_bytes());
+        tagged.extend(app1);
+        tagged.extend(&encoded[2..]);
+        let path = dir.join(format!("oriented_{orientation}.jpg"));
+        fs::write(&path, tagged).unwrap();
+        path
+    }
+
+    #[test]
+    fn exif_orientation_is_baked_into_the_output() {
+        let dir = test_dir("exif_orientation");
+        // 6 turns the left of the stored pixels to the top, 8 to the bottom.
+        for (orientation, top, bottom) in
+            [(6, [255, 0, 0], [0, 0, 255]), (8, [0, 0, 255], [255, 0, 0])]
+        {
+            let input = oriented_jpeg(&dir, orientation);
+            for output in [dir.join("out.png"), dir.join("out.jpg")] {
+                let details = frame_image(
+                    &input,
+                    &output,
+                    Sizing::Percent(100.0),
+                    &FramerOptions::default(),
+                )
+                .unwrap();
+                assert_eq!(details.framed, (20, 40));
+                let mut decoder = ImageReader::open(&output).unwrap().into_decoder().unwrap();
+                let exif = decoder.exif_metadata().unwrap();
+                assert!(
+                    exif.is_none_or(|data| exif::parse(&data).orientation.is_none_or(|o| o == 1))
+                );
+                let framed = DynamicImage::from_decoder(decoder).unwrap().into_rgb8();
+                for (y, expected) in [(5, top), (35, bottom)] {
+                    let pixel = framed.get_pixel(10, y).0;
+                    assert!(
+                        pixel.iter().zip(expected).all(|(a, b)| a.abs_diff(b) <= 8),
+                        "{pixel:?} at y {y} for orientation {orientation}"
+                    );
+                }
+            }
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn framing_errors_name_their_cause() {
+        let dir = test_dir("framing_errors");
+        let input = solid_png(&dir, "in.png", (50, 40), [0, 0, 0]);
+        let output = dir.join("out.png");
+        let frame = |options: FramerOptions| {
+            frame_image(&input, &output, Sizing::Dimensions(100, 100), &options)
+        };
+        assert!(matches!(
+            frame(FramerOptions {
+                crop: Some((10, 10, 50, 20)),
+                ..Default::default()
+            }),
+            Err(FramerError::CropOutOfBounds(50, 40))
+        ));
+        assert!(matches!(
+            frame(FramerOptions {
+                min_input_size: Some((60, 60)),
+                strict_min_size: true,
+                ..Default::default()
+            }),
+            Err(FramerError::Undersized(50, 40))
+        ));
+        assert!(matches!(
+            frame(FramerOptions {
+                small_image: SmallImage::Skip,
+                ..Default::default()
+            }),
+            Err(FramerError::Skipped(_))
+        ));
+        let bad = dir.join("bad.png");
+        fs::write(&bad, b"not a png").unwrap();
+        assert!(matches!(
+            frame_image(
+                &bad,
+                &output,
+                Sizing::Percent(100.0),
+                &FramerOptions::default()
+            ),
+            Err(FramerError::Image(_))
+        ));
+        assert!(matches!(
+            directory_files(&dir.join("missing")),
+            Err(FramerError::Io(_))
+        ));
+        frame(FramerOptions::default()).unwrap();
+        assert!(matches!(
+            verify(&output, (1, 1)),
+            Err(FramerError::Unverified(_))
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn planning_errors_name_their_cause() {
+        let dir = test_dir("planning_errors");
+        let options = FramerOptions::default();
+        let a = solid_png(&dir, "a.png", (8, 6), [0, 0, 0]);
+        let results = frame_files(
+            vec![a.clone()],
+            Some(&dir),
+            Sizing::Percent(100.0),
+            &options,
+        );
+        assert!(matches!(results[0].1, Err(FramerError::OverwritesSource)));
+        let outputs = plan_outputs(&[PathBuf::from("/")], Some(&dir), &options);
+        assert!(matches!(outputs[0], Err(FramerError::NoFileName)));
+        assert!(matches!(
+            output_format(Path::new("out.tiff2")),
+            Err(FramerError::UnsupportedOutput(ref extension)) if extension == "tiff2"
+        ));
+        let jpg = solid_png(&dir, "a.jpg", (8, 6), [0, 0, 0]);
+        let options = FramerOptions {
+            output_extension: Some("webp".into()),
+            ..Default::default()
+        };
+        let outputs = plan_outputs(&[a.clone(), jpg], Some(&dir.join("out")), &options);
+        assert!(matches!(outputs[1], Err(FramerError::OutputCollision(ref other)) if *other == a));
+        let copy = solid_png(&dir, "b.png", (8, 6), [0, 0, 0]);
+        let options = FramerOptions {
+            dedup: Some(DedupMode::Skip),
+            ..Default::default()
+        };
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        let results = frame_files(
+            vec![a.clone(), copy],
+            Some(&out),
+            Sizing::Percent(100.0),
+            &options,
+        );
+        assert!(
+            matches!(results[1].1, Err(FramerError::Duplicate(ref original)) if *original == a)
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(not(feature = "raw"))]
+    #[test]
+    fn raw_files_need_the_raw_feature() {
+        assert!(matches!(
+            check_support(Path::new("photo.nef")),
+            Err(FramerError::MissingFeature("camera RAW", "raw"))
+        ));
+    }
+
+    #[test]
+    fn decode_cache_keeps_images_until_their_last_job() {
+        let (twice, once) = (PathBuf::from("twice.png"), PathBuf::from("once.png"));
+        let cache = DecodeCache::new([&twice, &once, &twice]);
+        let img = DynamicImage::ImageRgb8(RgbImage::new(4, 3));
+        // The first job of `twice` decodes it for the second.
+        assert!(cache.take(&twice).is_none());
+        cache.insert(&twice, &img);
+        assert!(cache.take(&twice).is_some());
+        assert!(cache.take(&twice).is_none());
+        // Nothing is left to take the only image of `once`.
+        assert!(cache.take(&once).is_none());
+        cache.insert(&once, &img);
+        assert!(cache.take(&once).is_none());
+        assert_eq!(cache.state.lock().unwrap().bytes, 0);
+    }
+
+    #[test]
+    fn same_named_outputs_share_a_temp_dir() {
+        let dir = test_dir("shared_temp_dir");
+        let options = FramerOptions {
+            temp_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+        thread::scope(|scope| {
+            for name in ["one", "two"] {
+                let output = dir.join(name).join("a.png");
+                fs::create_dir_all(output.parent().unwrap()).unwrap();
+                let options = &options;
+                scope.spawn(move || {
+                    for i in 0..100 {
+                        let data = format!("{name} {i}");
+                        write_atomically(&output, data.as_bytes(), options).unwrap();
+                        assert_eq!(fs::read_to_string(&output).unwrap(), data);
+                    }
+                });
+            }
+        });
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    /// Options framing a 200x300 photo at 1:1 into a 320x320 canvas whose brand bar,
+    /// taller than the caption space below the margin, would cover the photo.
+    fn overflowing_options(overflow: Overflow) -> FramerOptions {
+        FramerOptions {
+            margin: Some(Margin {
+                pixels: 10,
+                percent: 0.0,
+            }),
+            caption_space: Some(Length::Pixels(0)),
+            brand_bar: Some(BrandBar {
+                height: Length::Percent(10.0),
+                position: BarPosition::Bottom,
+                color: Rgb([0, 0, 0]),
+                text: None,
+                text_color: Rgb([255, 255, 255]),
+                logo: None,
+            }),
+            overflow,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn overflow_error_rejects_a_covered_photo() {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(200, 300));
+        let result = frame_image_buffer(
+            img,
+            Sizing::AspectRatio(1.0, 1.0),
+            &overflowing_options(Overflow::Error),
+        );
+        assert!(matches!(
+            result,
+            Err(FramerError::Overflow((200, 300), (300, 268)))
+        ));
+    }
+
+    #[test]
+    fn overflow_shrink_fits_the_photo_above_the_bar() {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(200, 300));
+        let framed = frame_image_buffer(
+            img,
+            Sizing::AspectRatio(1.0, 1.0),
+            &overflowing_options(Overflow::Shrink),
+        )
+        .unwrap();
+        assert_eq!((framed.width, framed.height), (320, 320));
+        let (x, y, w, h) = framed.photo;
+        assert_eq!((w, h), (178, 267));
+        assert_eq!((x, y), (71, 10));
+        // The margin is kept between the photo and the 32 pixel bar.
+        assert!(y + h as i64 + 10 <= 320 - 32);
+    }
+
+    #[test]
+    fn overflow_expand_grows_the_canvas_past_the_bar() {
+        let img = DynamicImage::ImageRgb8(RgbImage::new(200, 300));
+        let framed = frame_image_buffer(
+            img,
+            Sizing::AspectRatio(1.0, 1.0),
+            &overflowing_options(Overflow::Expand),
+        )
+        .unwrap();
+        let bar_h = (framed.height as f32 * 0.1).round() as i64;
+        let (_, y, _, h) = framed.photo;
+        assert_eq!(framed.width, 320);
+        assert_eq!(y, 10);
+        assert!(y + h as i64 + 10 <= framed.height as i64 - bar_h);
+    }
+}
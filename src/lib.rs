@@ -0,0 +1,11 @@
+//! Frames photos onto a canvas of a fixed aspect ratio, with a border, background and
+//! optional brand bar, date stamp and color grading, as used by the `photo_framer`
+//! command line tool.
+
+mod exif;
+mod font;
+pub mod framer;
+pub mod logging;
+pub mod lut;
+mod metadata;
+mod raw;
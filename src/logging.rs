@@ -0,0 +1,241 @@
+use std::{cell::RefCell, fmt::Write as _, io::Write as _};
+
+use clap::ValueEnum;
+use log::{
+    Level, LevelFilter, Log, Metadata, Record,
+    kv::{self, Key, ToValue, Value, VisitSource, VisitValue},
+};
+
+thread_local! {
+    static CAPTURED: RefCell<Option<Vec<Message>>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines, with structured fields appended as `key=value`.
+    Human,
+    /// One JSON object per line.
+    Json,
+}
+
+/// An owned copy of a structured field, so it can outlive its record.
+enum Field {
+    Unsigned(u64),
+    Signed(i64),
+    Float(f64),
+    Bool(bool),
+    Null,
+    Text(String),
+}
+
+impl Field {
+    fn new(value: &Value) -> Field {
+        let mut field = Field::Null;
+        let _ = value.visit(&mut field);
+        field
+    }
+}
+
+impl<'v> VisitValue<'v> for &mut Field {
+    fn visit_any(&mut self, value: Value) -> Result<(), kv::Error> {
+        **self = Field::Text(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        **self = Field::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        **self = Field::Unsigned(value);
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        **self = Field::Signed(value);
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        **self = Field::Float(value);
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        **self = Field::Bool(value);
+        Ok(())
+    }
+}
+
+impl ToValue for Field {
+    fn to_value(&self) -> Value<'_> {
+        match self {
+            Field::Unsigned(value) => Value::from(*value),
+            Field::Signed(value) => Value::from(*value),
+            Field::Float(value) => Value::from(*value),
+            Field::Bool(value) => Value::from(*value),
+            Field::Null => Value::null(),
+            Field::Text(value) => Value::from(value.as_str()),
+        }
+    }
+}
+
+/// A log record held back while a file is being processed.
+pub struct Message {
+    level: Level,
+    target: String,
+    text: String,
+    fields: Vec<(String, Field)>,
+}
+
+struct FieldCollector(Vec<(String, Field)>);
+
+impl<'kvs> VisitSource<'kvs> for FieldCollector {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push((key.to_string(), Field::new(&value)));
+        Ok(())
+    }
+}
+
+/// Wraps `env_logger`, diverting records into a per-thread buffer while
+/// [`capture`] is running so that the messages of one file can be flushed
+/// together instead of interleaving with other jobs.
+struct BufferedLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for BufferedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        let unbuffered = CAPTURED.with_borrow_mut(|captured| match captured {
+            Some(messages) => {
+                let mut fields = FieldCollector(Vec::new());
+                let _ = record.key_values().visit(&mut fields);
+                messages.push(Message {
+                    level: record.level(),
+                    target: record.target().to_string(),
+                    text: record.args().to_string(),
+                    fields: fields.0,
+                });
+                false
+            }
+            None => true,
+        });
+        if unbuffered {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Appends `value` to `out` as a JSON string literal.
+pub fn push_json_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct JsonFields<'a>(&'a mut String);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push(',');
+        push_json_string(self.0, key.as_str());
+        self.0.push(':');
+        match Field::new(&value) {
+            Field::Unsigned(value) => {
+                let _ = write!(self.0, "{value}");
+            }
+            Field::Signed(value) => {
+                let _ = write!(self.0, "{value}");
+            }
+            Field::Float(value) if value.is_finite() => {
+                let _ = write!(self.0, "{value}");
+            }
+            Field::Float(_) | Field::Null => self.0.push_str("null"),
+            Field::Bool(value) => {
+                let _ = write!(self.0, "{value}");
+            }
+            Field::Text(value) => push_json_string(self.0, &value),
+        }
+        Ok(())
+    }
+}
+
+/// Installs the global logger, configured through `RUST_LOG` like `env_logger::init`.
+pub fn init(format: LogFormat, level: Option<LevelFilter>) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    if let LogFormat::Json = format {
+        builder.format(|buf, record| {
+            let mut line = String::from("{\"timestamp\":");
+            push_json_string(&mut line, &buf.timestamp().to_string());
+            line.push_str(",\"level\":");
+            push_json_string(&mut line, record.level().as_str());
+            line.push_str(",\"target\":");
+            push_json_string(&mut line, record.target());
+            line.push_str(",\"message\":");
+            push_json_string(&mut line, &record.args().to_string());
+            let _ = record.key_values().visit(&mut JsonFields(&mut line));
+            line.push('}');
+            writeln!(buf, "{line}")
+        });
+    }
+    let inner = builder.build();
+    let max_level = inner.filter();
+    log::set_boxed_logger(Box::new(BufferedLogger { inner }))
+        .expect("the logger is only installed once");
+    log::set_max_level(max_level);
+}
+
+/// Runs `f`, returning its result along with everything it logged on this thread.
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<Message>) {
+    CAPTURED.set(Some(Vec::new()));
+    let result = f();
+    let messages = CAPTURED.take().unwrap_or_default();
+    (result, messages)
+}
+
+/// Logs previously captured messages back to back.
+pub fn flush(messages: Vec<Message>) {
+    let logger = log::logger();
+    for message in messages {
+        let fields: Vec<(&str, &Field)> = message
+            .fields
+            .iter()
+            .map(|(key, field)| (key.as_str(), field))
+            .collect();
+        logger.log(
+            &Record::builder()
+                .level(message.level)
+                .target(&message.target)
+                .args(format_args!("{}", message.text))
+                .key_values(&fields)
+                .build(),
+        );
+    }
+}
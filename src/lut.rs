@@ -0,0 +1,170 @@
+//! 3D color lookup tables in the `.cube` format, as exported by grading tools and
+//! shipped with film emulations, applied with trilinear interpolation.
+
+use std::{fs, path::Path};
+
+use image::{DynamicImage, Rgb32FImage, RgbImage, Rgba32FImage};
+
+use crate::framer::FramerError;
+
+/// Largest `LUT_3D_SIZE` accepted. Common tables are 17, 33 or 65 entries per side.
+const MAX_SIZE: usize = 256;
+
+pub struct Lut {
+    /// Number of entries along each axis.
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    /// Output colors, with red changing fastest, then green, then blue.
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut {
+    /// Reads and parses the `.cube` file at `path`.
+    pub fn load(path: &Path) -> Result<Lut, FramerError> {
+        let text = fs::read_to_string(path)?;
+        Lut::parse(&text)
+    }
+
+    /// Parses the contents of a `.cube` file holding a 3D table.
+    pub fn parse(text: &str) -> Result<Lut, FramerError> {
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error =
+                |message: &str| FramerError::InvalidLut(format!("line {}: {message}", number + 1));
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err(error("1D tables are not supported")),
+                "LUT_3D_SIZE" => {
+                    let n = rest
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|_| error("LUT_3D_SIZE is not an integer"))?;
+                    if !(2..=MAX_SIZE).contains(&n) {
+                        return Err(error(&format!("LUT_3D_SIZE must be from 2 to {MAX_SIZE}")));
+                    }
+                    size = Some(n);
+                }
+                "DOMAIN_MIN" => {
+                    domain_min = triple(rest).ok_or_else(|| error("invalid DOMAIN_MIN"))?
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = triple(rest).ok_or_else(|| error("invalid DOMAIN_MAX"))?
+                }
+                _ => table.push(triple(line).ok_or_else(|| error("expected three numbers"))?),
+            }
+        }
+        let size = size.ok_or_else(|| FramerError::InvalidLut("LUT_3D_SIZE is missing".into()))?;
+        if table.len() != size * size * size {
+            return Err(FramerError::InvalidLut(format!(
+                "expected {} entries for LUT_3D_SIZE {size}, found {}",
+                size * size * size,
+                table.len()
+            )));
+        }
+        if (0..3).any(|channel| domain_min[channel] >= domain_max[channel]) {
+            return Err(FramerError::InvalidLut(String::from(
+                "DOMAIN_MIN must be below DOMAIN_MAX",
+            )));
+        }
+        Ok(Lut {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    /// Looks up `color`, with channels from 0 to 1, interpolating between the eight
+    /// surrounding entries. Colors outside the domain are clamped to it.
+    fn lookup(&self, color: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let mut base = [0; 3];
+        let mut fraction = [0.0; 3];
+        for channel in 0..3 {
+            let (min, max) = (self.domain_min[channel], self.domain_max[channel]);
+            let position = ((color[channel] - min) / (max - min)).clamp(0.0, 1.0) * last;
+            // The top entry is interpolated from the one below it, with a fraction of 1.
+            base[channel] = (position.floor() as usize).min(self.size - 2);
+            fraction[channel] = position - base[channel] as f32;
+        }
+        let entry = |r: usize, g: usize, b: usize| {
+            self.table
+                [(base[0] + r) + (base[1] + g) * self.size + (base[2] + b) * self.size * self.size]
+        };
+        let mut out = [0.0; 3];
+        for (r, g, b) in (0..8).map(|corner| (corner & 1, corner >> 1 & 1, corner >> 2 & 1)) {
+            let weight = [r, g, b]
+                .iter()
+                .zip(fraction)
+                .map(|(&offset, fraction)| {
+                    if offset == 1 {
+                        fraction
+                    } else {
+                        1.0 - fraction
+                    }
+                })
+                .product::<f32>();
+            let value = entry(r, g, b);
+            for channel in 0..3 {
+                out[channel] += weight * value[channel];
+            }
+        }
+        out
+    }
+
+    /// Returns `img` graded by the table, as floating point with any alpha kept.
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        if img.color().has_alpha() {
+            let mut graded = img.to_rgba32f();
+            for pixel in graded.pixels_mut() {
+                let [r, g, b] = self.lookup([pixel[0], pixel[1], pixel[2]]);
+                pixel.0 = [r, g, b, pixel[3]];
+            }
+            DynamicImage::ImageRgba32F(graded)
+        } else {
+            let mut graded: Rgb32FImage = img.to_rgb32f();
+            for pixel in graded.pixels_mut() {
+                pixel.0 = self.lookup(pixel.0);
+            }
+            DynamicImage::ImageRgb32F(graded)
+        }
+    }
+
+    /// Grades `img` in place.
+    pub fn apply_rgb8(&self, img: &mut RgbImage) {
+        for pixel in img.pixels_mut() {
+            let graded = self.lookup(pixel.0.map(|channel| channel as f32 / 255.0));
+            pixel.0 = graded.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+
+    /// Grades `img`, whose color is premultiplied by alpha, in place.
+    pub fn apply_premultiplied(&self, img: &mut Rgba32FImage) {
+        for pixel in img.pixels_mut() {
+            let alpha = pixel[3];
+            if alpha <= 0.0 {
+                continue;
+            }
+            let [r, g, b] = self.lookup([pixel[0] / alpha, pixel[1] / alpha, pixel[2] / alpha]);
+            pixel.0 = [r * alpha, g * alpha, b * alpha, alpha];
+        }
+    }
+}
+
+/// Parses three whitespace separated finite numbers.
+fn triple(s: &str) -> Option<[f32; 3]> {
+    let mut values = s
+        .split_whitespace()
+        .map(|value| value.parse::<f32>().ok().filter(|v| v.is_finite()));
+    let triple = [values.next()??, values.next()??, values.next()??];
+    values.next().is_none().then_some(triple)
+}
@@ -1,9 +1,36 @@
-use clap::{Parser, ValueEnum};
-use framer::{Sizing, frame_image};
-use log::error;
-use std::{path::PathBuf, process::exit};
+use clap::{
+    Arg, ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+    error::ErrorKind,
+};
+use image::{Rgb, imageops::FilterType};
+use log::{error, info, warn};
+use photo_framer::{
+    framer::{
+        self, AnimationFrame, Background, BatchCounts, BrandBar, DateStamp, FramerError,
+        FramerOptions, Length, Margin, OrientationHandling, Pattern, ResizeFilter, Sheet, Sizing,
+        directory_files, frame_image, frame_planned, output_path, parent_dir, plan_files,
+        same_path, stream_directory,
+    },
+    logging, lut,
+};
+use plan::Plan;
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fmt::Write as _,
+    fs::{self, File, TryLockError},
+    io::{self, IsTerminal},
+    iter,
+    path::{Path, PathBuf},
+    process::exit,
+};
 
-mod framer;
+mod plan;
+
+/// Blur sigma of the `--story` background, unless `--background-blur` is given.
+const STORY_BLUR: f32 = 30.0;
 
 #[derive(Clone, ValueEnum, Copy)]
 enum OutputType {
@@ -12,41 +39,1438 @@ enum OutputType {
     Webp,
 }
 
+#[derive(Clone, ValueEnum, Copy)]
+enum BrandPosition {
+    Top,
+    Bottom,
+}
+
+impl From<BrandPosition> for framer::BarPosition {
+    fn from(position: BrandPosition) -> Self {
+        match position {
+            BrandPosition::Top => framer::BarPosition::Top,
+            BrandPosition::Bottom => framer::BarPosition::Bottom,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum Sort {
+    Name,
+    Mtime,
+    Size,
+}
+
+impl From<Sort> for framer::SortKey {
+    fn from(sort: Sort) -> Self {
+        match sort {
+            Sort::Name => framer::SortKey::Name,
+            Sort::Mtime => framer::SortKey::Mtime,
+            Sort::Size => framer::SortKey::Size,
+        }
+    }
+}
+
+/// Paper sizes for `--sheet`.
+#[derive(Clone, ValueEnum, Copy)]
+enum Paper {
+    A4,
+    A5,
+    Letter,
+    #[value(name = "4x6")]
+    Photo4x6,
+    #[value(name = "5x7")]
+    Photo5x7,
+}
+
+impl Paper {
+    /// Width and height of the paper in portrait orientation, in inches.
+    fn inches(self) -> (f32, f32) {
+        match self {
+            Paper::A4 => (210.0 / 25.4, 297.0 / 25.4),
+            Paper::A5 => (148.0 / 25.4, 210.0 / 25.4),
+            Paper::Letter => (8.5, 11.0),
+            Paper::Photo4x6 => (4.0, 6.0),
+            Paper::Photo5x7 => (5.0, 7.0),
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum Dedup {
+    /// Frame near duplicates anyway, but report them.
+    Flag,
+    /// Leave near duplicates unframed.
+    Skip,
+}
+
+impl From<Dedup> for framer::DedupMode {
+    fn from(dedup: Dedup) -> Self {
+        match dedup {
+            Dedup::Flag => framer::DedupMode::Flag,
+            Dedup::Skip => framer::DedupMode::Skip,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum Filter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+    /// Averages the pixels each output pixel covers. Prefer it for downscaling
+    /// screenshots and other images with sharp lines, which `lanczos3` can make ring
+    /// or alias.
+    #[value(alias = "box")]
+    Area,
+}
+
+impl From<Filter> for ResizeFilter {
+    fn from(filter: Filter) -> Self {
+        let filter = match filter {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+            Filter::Area => return ResizeFilter::Area,
+        };
+        ResizeFilter::Convolution(filter)
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum MatchMode {
+    /// Use the chosen border color for every image.
+    Color,
+    /// Use a neutral gray matched to the image's brightness for grayscale images.
+    Mono,
+}
+
+impl From<MatchMode> for framer::MatchMode {
+    fn from(mode: MatchMode) -> Self {
+        match mode {
+            MatchMode::Color => framer::MatchMode::Color,
+            MatchMode::Mono => framer::MatchMode::Mono,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum SmallImage {
+    /// Leave the image unframed.
+    Skip,
+    /// Enlarge the image to fit the canvas.
+    Upscale,
+    /// Place the image at its native size in the middle of the canvas.
+    Center,
+    /// Keep the image at its native size and shrink the canvas around it, keeping
+    /// the requested aspect ratio.
+    ShrinkCanvas,
+}
+
+impl From<SmallImage> for framer::SmallImage {
+    fn from(small_image: SmallImage) -> Self {
+        match small_image {
+            SmallImage::Skip => framer::SmallImage::Skip,
+            SmallImage::Upscale => framer::SmallImage::Upscale,
+            SmallImage::Center => framer::SmallImage::Center,
+            SmallImage::ShrinkCanvas => framer::SmallImage::ShrinkCanvas,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum Overflow {
+    /// Scale the photo down to fit the canvas.
+    Shrink,
+    /// Grow the canvas to fit the photo, giving up the exact aspect ratio.
+    Expand,
+    /// Fail to frame the image.
+    Error,
+}
+
+impl From<Overflow> for framer::Overflow {
+    fn from(overflow: Overflow) -> Self {
+        match overflow {
+            Overflow::Shrink => framer::Overflow::Shrink,
+            Overflow::Expand => framer::Overflow::Expand,
+            Overflow::Error => framer::Overflow::Error,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum SummaryFormat {
+    /// Log lines at the `info` and `warn` levels.
+    Human,
+    /// A `RESULT processed=<n> framed=<n> ...` line on standard output.
+    Kv,
+    /// A JSON object on standard output.
+    Json,
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum DateSource {
+    /// The file's creation time, or its modification time where creation times aren't
+    /// recorded.
+    Filetime,
+}
+
+impl From<DateSource> for framer::DateSource {
+    fn from(source: DateSource) -> Self {
+        match source {
+            DateSource::Filetime => framer::DateSource::FileTime,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum, Copy)]
+enum Orientation {
+    /// Keep the pixels as stored, ignoring the EXIF orientation tag.
+    Ignore,
+    /// Rotate according to the tag, but warn and leave images unrotated when the tag is
+    /// invalid or contradicts the recorded dimensions.
+    Lenient,
+    /// Rotate according to the tag, failing images whose tag is invalid or contradicts
+    /// the recorded dimensions.
+    Strict,
+}
+
+impl From<Orientation> for OrientationHandling {
+    fn from(orientation: Orientation) -> Self {
+        match orientation {
+            Orientation::Ignore => OrientationHandling::Ignore,
+            Orientation::Lenient => OrientationHandling::Lenient,
+            Orientation::Strict => OrientationHandling::Strict,
+        }
+    }
+}
+
 #[derive(Parser)]
-#[command(version, about)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Without a command, images are framed as with `frame`.
+    #[command(flatten)]
+    frame: FrameArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Frame images. This is also what runs when no command is given.
+    Frame(FrameArgs),
+    /// Convert images to the output filetype at their native size, without a border.
+    Convert(FrameArgs),
+}
+
+impl Cli {
+    /// Returns the arguments of the given command, as arguments of `frame`.
+    fn into_frame_args(self) -> FrameArgs {
+        match self.command {
+            None => self.frame,
+            Some(Command::Frame(args)) => args,
+            Some(Command::Convert(mut args)) => {
+                let sizing = [
+                    ("--aspect-ratio", args.aspect_ratio.is_some()),
+                    ("--dimensions", args.dimensions.is_some()),
+                    ("--story", args.story),
+                    ("--caption-space", args.caption_space.is_some()),
+                    ("--brand-bar", args.brand_bar.is_some()),
+                ];
+                if let Some((flag, _)) = sizing.iter().find(|(_, given)| *given) {
+                    Cli::command()
+                        .error(
+                            ErrorKind::ArgumentConflict,
+                            format!("the argument '{flag}' cannot be used with 'convert'"),
+                        )
+                        .exit();
+                }
+                args.convert_only = true;
+                args
+            }
+        }
+    }
+}
+
+#[derive(Args)]
+struct FrameArgs {
     /// Input folder or image.
-    #[arg(short, long)]
-    input: PathBuf,
+    #[arg(short, long, required_unless_present_any = ["input_list", "from_plan", "jobs_file"], value_parser = expand_path)]
+    input: Option<PathBuf>,
 
-    /// Output directory.
-    #[arg(short, long)]
-    output: PathBuf,
+    /// File listing input images, one path or glob pattern (e.g. `shoot1/*.jpg`) per line.
+    /// Blank lines and lines starting with `#` are ignored.
+    #[arg(long, conflicts_with = "input", value_parser = expand_path)]
+    input_list: Option<PathBuf>,
+
+    /// Output directory. If omitted, each framed image is written next to its source,
+    /// which requires `--suffix` so the originals are not overwritten.
+    #[arg(short, long, value_parser = expand_path)]
+    output: Option<PathBuf>,
 
-    /// Aspect ratio to use. Should be provided in the format `<width>:<height>`.
-    /// For example: `16:9`, `1:1`, `4.3:2`.
+    /// Order in which images are framed. Folders are framed in name order by default,
+    /// and input lists in the order of the file.
+    #[arg(long, value_enum)]
+    sort: Option<Sort>,
+
+    /// Reverse the order images are framed in.
+    #[arg(long)]
+    sort_desc: bool,
+
+    /// Name outputs of an input list after the directories leading to each input, such as
+    /// `2023_jan_img.jpg` for `2023/jan/img.jpg`, so same-named files of different
+    /// directories don't collide in the output directory.
+    #[arg(long, requires = "output")]
+    flatten_output: bool,
+
+    /// Frame the images of a folder in the order they are read, without first listing the
+    /// whole folder. This saves memory on folders with a very large number of files.
+    #[arg(long, conflicts_with_all = ["input_list", "sort", "sort_desc", "uniform_size", "dedup"])]
+    streaming: bool,
+
+    /// Write the images a folder or input list would be framed into, and the arguments
+    /// they would be framed with, to a JSON file and exit without framing anything. The
+    /// plan can be reviewed or edited, then run with `--from-plan`.
+    #[arg(long, conflicts_with = "streaming", value_parser = expand_path)]
+    plan_out: Option<PathBuf>,
+
+    /// Frame the images of a plan written by `--plan-out` into the outputs it lists, with
+    /// the arguments it was written with, without reading the folder or input list again.
+    /// Options that don't change what is framed into where, such as `--yes`, `--verbose`
+    /// or `--jobs`, can be given along with it and apply on top of those of the plan.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "input", "input_list", "output", "output_filetype", "plan_out", "streaming",
+            "aspect_ratio", "dimensions", "story", "convert_only", "ratios_from",
+        ],
+        value_parser = expand_path
+    )]
+    from_plan: Option<PathBuf>,
+
+    /// Tab-separated file of jobs, one `<input>\t<output>` line per image with an optional
+    /// third column giving its sizing like `--ratios-from` does. Exactly these images are
+    /// framed into exactly these outputs, whose directories must exist, so one image can
+    /// be framed at several sizings. Blank lines and lines starting with `#` are ignored,
+    /// and invalid lines fail on their own.
+    #[arg(
+        long,
+        conflicts_with_all = ["input", "input_list", "output", "streaming", "plan_out", "from_plan", "ratios_from"],
+        value_parser = expand_path
+    )]
+    jobs_file: Option<PathBuf>,
+
+    /// Detect perceptually similar images of a folder or input list, such as near
+    /// identical shots, and report or skip all but the first of them. Images are decoded
+    /// ahead of framing for this, and kept decoded for framing while memory allows. The
+    /// mode is given as `--dedup=skip`, so a bare `--dedup` reports them.
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "flag"
+    )]
+    dedup: Option<Dedup>,
+
+    /// Number of bits, out of 64, in which the perceptual hashes of two images may differ
+    /// for them to count as duplicates.
+    #[arg(long, default_value_t = 6, requires = "dedup")]
+    dedup_threshold: u32,
+
+    /// Create the output directory, including missing parents, if it does not exist.
+    #[arg(short = 'p', long, requires = "output")]
+    create_output_dir: bool,
+
+    /// Text appended to the file name of each framed image.
+    /// For example, `_framed` writes `photo.jpg` as `photo_framed.jpg`.
+    #[arg(long)]
+    suffix: Option<String>,
+
+    /// Aspect ratio to use. Should be provided in the format `<width>:<height>`, or as a
+    /// single number taken as `<width>:1`. For example: `16:9`, `1:1`, `4.3:2`, `1.5`.
     #[arg(long, alias = "ratio")]
     aspect_ratio: Option<String>,
 
+    /// Snap the aspect ratio to the nearest common photographic ratio (such as 3:2 or
+    /// 16:9) if it is within 3% of one.
+    #[arg(long, requires = "aspect_ratio")]
+    snap_ratio: bool,
+
+    /// Crop images whose aspect ratio is within this many percent of the requested
+    /// ratio to match it exactly, instead of adding a thin border.
+    #[arg(long, default_value_t = 0.0, requires = "aspect_ratio")]
+    snap_threshold: f32,
+
+    /// Most elongated aspect ratio a photo keeps, such as `3:1`, applying to portrait
+    /// photos as `1:3` too. Photos more elongated than it, like panoramas, have their long
+    /// side cropped to it around the center, and only then are padded to `--aspect-ratio`,
+    /// so the canvas isn't mostly border.
+    #[arg(long, requires = "aspect_ratio")]
+    max_aspect: Option<String>,
+
     /// Output image dimension to use. Should be provided in the format `<width>x<height>`.
     /// For example: `1920x1080`, `1080x1080`, `720x1500`.
+    /// A percentage such as `50%` instead scales the image without adding a border.
     #[arg(long, alias = "dim")]
     dimensions: Option<String>,
 
+    /// Only convert the images to the output filetype, at their native size and without
+    /// a border.
+    #[arg(
+        long,
+        conflicts_with_all = ["aspect_ratio", "dimensions", "story", "caption_space", "brand_bar"]
+    )]
+    convert_only: bool,
+
+    /// Widen the border so that both dimensions of the output are multiples of this
+    /// number, for example `16` for video encoders. The photo stays centered.
+    #[arg(long)]
+    pad_to_multiple: Option<u32>,
+
+    /// Enlarge framed images, border included, whose shorter side is below this many
+    /// pixels, such as for platforms that reject small uploads.
+    #[arg(long)]
+    min_dimension: Option<u32>,
+
+    /// Smallest border on every side of the photo: pixels such as `16`, a percentage such
+    /// as `3%`, or the larger of both as `max(16px, 3%)`, which keeps borders visible on
+    /// small images and proportional on large ones. The percentage is of the longer side
+    /// of the output with `--dimensions`, and of the photo otherwise.
+    #[arg(long)]
+    margin: Option<String>,
+
+    /// Fraction of the canvas, after any caption space, the photo fills in each
+    /// direction, from above 0 to 1. For example `0.8` leaves a border of at least 10% on
+    /// every side, growing with the canvas.
+    #[arg(long, default_value_t = 1.0)]
+    fill_ratio: f32,
+
+    /// Tile copies of each framed image onto a print sheet, in the format
+    /// `<columns>x<rows>`. For example `2x2` for four passport photos on one page. Cut
+    /// guides are drawn between the copies.
+    #[arg(long)]
+    multi_up: Option<String>,
+
+    /// Paper size of the `--multi-up` sheet, which is turned to landscape when it has more
+    /// columns than rows.
+    #[arg(long, value_enum, default_value = "a4", requires = "multi_up")]
+    sheet: Paper,
+
+    /// Resolution of the `--multi-up` sheet in dots per inch.
+    #[arg(long, default_value_t = 300, requires = "multi_up")]
+    sheet_dpi: u32,
+
+    /// Warn about inputs smaller than this size, in the format `<width>x<height>`, which
+    /// would look soft when enlarged into the frame.
+    #[arg(long)]
+    min_input_size: Option<String>,
+
+    /// Fail inputs smaller than `--min-input-size` instead of warning about them.
+    #[arg(long, requires = "min_input_size")]
+    strict_min_size: bool,
+
+    /// CSV file giving the sizing of individual images, one `<file name>,<sizing>` row per
+    /// image, where the sizing is an aspect ratio such as `4:5`, dimensions such as
+    /// `1080x1350` or a percentage. Images without a row use the global sizing.
+    #[arg(long, conflicts_with = "uniform_size", value_parser = expand_path)]
+    ratios_from: Option<PathBuf>,
+
+    /// Skip images without a row in `--ratios-from`, which makes the global sizing
+    /// optional.
+    #[arg(long, requires = "ratios_from")]
+    require_mapping: bool,
+
+    /// Crop each photo to this rectangle before framing it, in the format
+    /// `<x>,<y>,<width>,<height>` in pixels from the top-left corner of the upright photo.
+    /// For example: `120,80,1600,1200`. Photos the rectangle doesn't fit in fail.
+    #[arg(long)]
+    crop: Option<String>,
+
+    /// Also write a before and after comparison of each output next to it, named with a
+    /// `_comparison` suffix, for proofing the framing style.
+    #[arg(long)]
+    comparison: bool,
+
+    /// Decode each output after writing it and fail those that don't decode to the framed
+    /// dimensions, catching corruption by the encoder or the disk.
+    #[arg(long)]
+    verify: bool,
+
+    /// Delete outputs that fail `--verify`.
+    #[arg(long, requires = "verify")]
+    delete_unverified: bool,
+
     /// Output filetype to use. If not provided, the filetype of the input image will be used.
     #[arg(value_enum, alias = "type")]
     output_filetype: Option<OutputType>,
+
+    /// Border color as a hex code. For example: `#ffffff`, `#000`, `1e1e1e`.
+    #[arg(long, default_value = "#ffffff")]
+    color: String,
+
+    /// Border color as red, green and blue values from 0 to 255, instead of `--color`.
+    /// Should be provided in the format `<red>,<green>,<blue>`. For example: `30,30,30`.
+    #[arg(long, conflicts_with_all = [
+        "color",
+        "background_pattern",
+        "background_blur",
+        "story",
+        "background_pixel",
+        "background_edge_match",
+    ])]
+    background_rgb: Option<String>,
+
+    /// Border color for landscape images. Defaults to `--color`.
+    #[arg(long, conflicts_with = "background_pattern")]
+    color_landscape: Option<String>,
+
+    /// Border color for portrait images. Defaults to `--color`.
+    #[arg(long, conflicts_with = "background_pattern")]
+    color_portrait: Option<String>,
+
+    /// How the border color is matched to each image.
+    #[arg(
+        long,
+        value_enum,
+        default_value = "color",
+        conflicts_with = "background_pattern"
+    )]
+    match_mode: MatchMode,
+
+    /// Color that transparent parts of the input are flattened onto. Defaults to the
+    /// border color, so transparent areas blend into the border.
+    #[arg(long)]
+    flatten_color: Option<String>,
+
+    /// Line drawn along the edge of the whole output, for example as a guide for cutting
+    /// prints. Should be provided in the format `<width>,<color>`.
+    /// For example: `2,#000000`. A width of `0` disables the line.
+    #[arg(long)]
+    edge_stroke: Option<String>,
+
+    /// Fill the border with a blurred copy of the photo scaled to cover the canvas,
+    /// using this blur sigma in pixels. For example: `20`.
+    #[arg(long, conflicts_with = "background_pattern")]
+    background_blur: Option<f32>,
+
+    /// Fill each bar around the photo with the average color of the photo's facing edge,
+    /// giving two-tone borders that blend into the photo.
+    #[arg(
+        long,
+        conflicts_with_all = ["background_pattern", "background_blur", "story", "background_pixel", "color_landscape", "color_portrait"]
+    )]
+    background_edge_match: bool,
+
+    /// Fill the border with the color of the photo's pixel at these coordinates, in the
+    /// format `<x>,<y>` from its top-left corner. For example: `10,10` to match a studio
+    /// backdrop. Coordinates past the edges are clamped to them.
+    #[arg(
+        long,
+        conflicts_with_all = ["background_pattern", "background_blur", "story", "color_landscape", "color_portrait"]
+    )]
+    background_pixel: Option<String>,
+
+    /// Only blur the bars around the photo for `--background-blur` and `--story`, which
+    /// is faster than blurring the whole background.
+    #[arg(long)]
+    blur_bars: bool,
+
+    /// Space reserved at the bottom of the output, for example for a caption, in pixels
+    /// or as a percentage of the output height. For example: `200`, `15%`.
+    #[arg(long)]
+    caption_space: Option<String>,
+
+    /// Solid bar across the output holding `--brand-logo` and `--brand-text`, in pixels
+    /// or as a percentage of the output height. It is drawn over the output. With
+    /// `--aspect-ratio`, a photo it would cover is handled as set by `--overflow`; other
+    /// modes need `--caption-space` to keep it clear of the photo.
+    #[arg(long)]
+    brand_bar: Option<String>,
+
+    /// Edge of the output the brand bar runs along.
+    #[arg(long, value_enum, default_value = "bottom", requires = "brand_bar")]
+    brand_position: BrandPosition,
+
+    /// Color of the brand bar.
+    #[arg(long, default_value = "#000000", requires = "brand_bar")]
+    brand_color: String,
+
+    /// Logo drawn at the start of the brand bar, scaled to fit it.
+    #[arg(long, requires = "brand_bar", value_parser = expand_path)]
+    brand_logo: Option<PathBuf>,
+
+    /// Text written in the brand bar, after the logo.
+    #[arg(long, requires = "brand_bar")]
+    brand_text: Option<String>,
+
+    /// Color of the brand text.
+    #[arg(long, default_value = "#ffffff", requires = "brand_bar")]
+    brand_text_color: String,
+
+    /// Draw the date of each image in the lower right of the border, below the photo or
+    /// beside it, whichever leaves more room.
+    #[arg(long, value_enum)]
+    date_stamp: Option<DateSource>,
+
+    /// Pattern of the date stamp, where `%Y`, `%y`, `%m`, `%d`, `%H`, `%M` and `%S` stand
+    /// for the parts of the date and time in UTC.
+    #[arg(long, default_value = "%Y-%m-%d", requires = "date_stamp")]
+    date_format: String,
+
+    /// Color of the date stamp.
+    #[arg(long, default_value = "#ff8c00", requires = "date_stamp")]
+    date_color: String,
+
+    /// 3D color lookup table in the `.cube` format to grade each photo with, such as a
+    /// film emulation. Only the photo is graded, unless `--lut-background` is given.
+    #[arg(long, value_parser = expand_path)]
+    lut: Option<PathBuf>,
+
+    /// Also grade the backgrounds and border colors derived from the photo, such as
+    /// `--background-blur`, with `--lut`.
+    #[arg(long, requires = "lut")]
+    lut_background: bool,
+
+    /// Preset for phone story backgrounds: a 9:16 output with a blurred background.
+    /// Combine with `--caption-space` to leave room for a caption.
+    #[arg(long, conflicts_with_all = ["aspect_ratio", "dimensions", "background_pattern"])]
+    story: bool,
+
+    /// Image to tile repeatedly across the border instead of a solid color.
+    #[arg(long, value_parser = expand_path)]
+    background_pattern: Option<PathBuf>,
+
+    /// Scale factor applied to the pattern tile before tiling.
+    #[arg(long, default_value_t = 1.0, requires = "background_pattern")]
+    pattern_scale: f32,
+
+    /// Offset of the pattern tile in pixels. Should be provided in the format `<x>,<y>`.
+    /// For example: `0,0`, `16,32`.
+    #[arg(long, requires = "background_pattern")]
+    pattern_offset: Option<String>,
+
+    /// Embed a JPEG preview of each output, at most this many pixels on its longer side,
+    /// as the EXIF thumbnail file managers and viewers show before decoding the image.
+    /// Supported for `jpeg` outputs. For example `160`.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    embed_thumbnail: Option<u32>,
+
+    /// Write a comment noting photo_framer and the applied settings into each output's
+    /// metadata. Supported for `jpeg` and `png` outputs.
+    #[arg(long)]
+    tag_producer: bool,
+
+    /// How to handle images smaller than the requested dimensions.
+    #[arg(long, value_enum, default_value = "upscale", requires = "dimensions")]
+    small_image: SmallImage,
+
+    /// Keep images at their native resolution in dimensions mode, centering them on the
+    /// canvas. Images larger than the canvas are cropped around their center.
+    #[arg(long, requires = "dimensions", conflicts_with = "small_image")]
+    no_resize: bool,
+
+    /// Frame every image of a folder or input list into an identical canvas: the given
+    /// dimensions, or the smallest canvas of the aspect ratio that fits the largest
+    /// image. Smaller images get a wider border instead of being enlarged.
+    #[arg(long, conflicts_with = "small_image")]
+    uniform_size: bool,
+
+    /// What happens when a photo would be clipped by its aspect ratio canvas, as can
+    /// happen by a pixel through rounding, or be covered by a brand bar taller than
+    /// `--caption-space`.
+    #[arg(long, value_enum, default_value = "expand", requires = "aspect_ratio")]
+    overflow: Overflow,
+
+    /// How images are rotated according to their EXIF orientation tag.
+    #[arg(long, value_enum, default_value = "lenient")]
+    orientation: Orientation,
+
+    /// Frame of animated GIF and WebP inputs to frame: `first`, `middle`, `last` or a
+    /// zero-based index.
+    #[arg(long, default_value = "first")]
+    frame: String,
+
+    /// Filter used when resizing images.
+    #[arg(long, value_enum, default_value = "lanczos3")]
+    filter: Filter,
+
+    /// Filter used when images are enlarged. Defaults to `--filter`.
+    #[arg(long, value_enum)]
+    upscale_filter: Option<Filter>,
+
+    /// Filter used when images are shrunk. Defaults to `--filter`.
+    #[arg(long, value_enum)]
+    downscale_filter: Option<Filter>,
+
+    /// Filter used when scaling the photo to cover the canvas for `--background-blur` and
+    /// `--blur-bars`. Unlike the other filters it doesn't follow `--filter`, and defaults
+    /// to the fast `triangle`, since the result is blurred anyway.
+    #[arg(long, value_enum, default_value = "triangle")]
+    background_filter: Filter,
+
+    /// Favor speed over quality when downscaling: large reductions are box-averaged
+    /// first and the remainder uses the `triangle` filter. Useful for thumbnails.
+    #[arg(long, conflicts_with_all = ["filter", "downscale_filter"])]
+    fast: bool,
+
+    /// Format of log output. Logging is configured through the `RUST_LOG` environment
+    /// variable, and each processed image is logged at the `info` level with structured fields.
+    #[arg(long, value_enum, default_value = "human")]
+    log_format: logging::LogFormat,
+
+    /// Log more, overriding the default level of `RUST_LOG`: `-v` logs the summary and each
+    /// processed image, and `-vv` also how each image is decoded, converted and encoded.
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Format of the summary of a folder or input list. `kv` and `json` are printed to
+    /// standard output whatever the log level, for scripts and CI logs to parse.
+    #[arg(long, value_enum, default_value = "human")]
+    summary_format: SummaryFormat,
+
+    /// Print a JSON description of every option, for front ends built on the CLI, and exit.
+    #[arg(long, exclusive = true)]
+    describe_options: bool,
+
+    /// Attempt to frame every file regardless of its extension, relying on the file's
+    /// contents to detect its format and skipping those that aren't images.
+    #[arg(long)]
+    no_extension_filter: bool,
+
+    /// Warn about and skip a single input file of an unsupported filetype, exiting
+    /// successfully, like folders and input lists skip such files.
+    #[arg(long, conflicts_with = "no_extension_filter")]
+    skip_unsupported: bool,
+
+    /// Exit with an error if a folder or input list holds no image with an accepted
+    /// filetype, instead of only warning about it.
+    #[arg(long)]
+    fail_on_empty: bool,
+
+    /// Overwrite existing outputs of a folder or input list without asking. Otherwise a
+    /// run from a terminal that would overwrite files asks for confirmation first, except
+    /// with `--streaming`, which never asks.
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Don't lock the output directory. By default a second run writing into the same
+    /// directory exits instead of racing the first one on identical file names. The lock
+    /// is taken on a `.photo_framer.lock` file, which stays in the directory afterwards.
+    #[arg(long)]
+    no_lock: bool,
+
+    /// Directory outputs are written to before being renamed into place, so interrupted
+    /// runs don't leave partial outputs. Defaults to the output directory, as renames
+    /// from another filesystem are copies and so not atomic.
+    #[arg(long, value_parser = expand_path)]
+    temp_dir: Option<PathBuf>,
+
+    /// Number of images to frame in parallel.
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    jobs: u32,
 }
 
-fn main() {
-    let accepted_extensions = ["jpeg", "jpg", "png", "webp"];
-    env_logger::init();
+/// Expands a leading `~` to the home directory and `$VAR` or `${VAR}` to the value of
+/// the environment variable, so paths from scripts and aliases work unquoted. `$$` is a
+/// literal `$`, and `$` sequences that don't name a set variable, such as the `$1` of
+/// `photo$1.png`, are kept as written.
+fn expand_path(s: &str) -> Result<PathBuf, String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    if let Some(after) = s.strip_prefix('~')
+        && (after.is_empty() || after.starts_with(['/', std::path::MAIN_SEPARATOR]))
+        && let Ok(home) = env::var("HOME")
+    {
+        expanded.push_str(&home);
+        rest = after;
+    }
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(remainder) = after.strip_prefix('$') {
+            expanded.push('$');
+            rest = remainder;
+            continue;
+        }
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                after.split_at(end)
+            }
+        };
+        let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        match env::var(name) {
+            Ok(value) if is_name => {
+                expanded.push_str(&value);
+                rest = remainder;
+            }
+            _ => {
+                expanded.push('$');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+/// Takes an exclusive advisory lock on the lock file in `dir`, exiting if another run
+/// holds it. The lock is released when the returned file is dropped, while the file
+/// itself is kept.
+fn lock_output_dir(dir: &Path) -> Option<File> {
+    let path = dir.join(framer::LOCK_FILE);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .unwrap_or_else(|e| {
+            error!("Unable to create lock file {}: {e}", path.display());
+            exit(exitcode::CANTCREAT);
+        });
+    match file.try_lock() {
+        Ok(()) => Some(file),
+        Err(TryLockError::WouldBlock) => {
+            error!(
+                "Another run is already writing into {}. Pass `--no-lock` to run anyway.",
+                dir.display()
+            );
+            exit(exitcode::TEMPFAIL);
+        }
+        Err(TryLockError::Error(e)) => {
+            warn!(
+                "Unable to lock {}, continuing without a lock: {e}",
+                path.display()
+            );
+            None
+        }
+    }
+}
+
+/// Reads an input list file, expanding each line as a glob pattern. Matches of a
+/// single line are sorted, while lines keep the order they have in the file.
+fn read_input_list(path: &PathBuf) -> Vec<PathBuf> {
+    let contents = fs::read_to_string(path).unwrap_or_else(|_| {
+        error!("Unable to read input list {}", path.display());
+        exit(exitcode::NOINPUT);
+    });
+    let mut files = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let paths = match glob::glob(line) {
+            Ok(paths) => paths,
+            Err(e) => {
+                error!(
+                    "Invalid pattern on line {} of input list: {}",
+                    number + 1,
+                    e
+                );
+                continue;
+            }
+        };
+        let mut matches: Vec<PathBuf> = paths
+            .filter_map(|entry| {
+                entry
+                    .inspect_err(|e| error!("Unable to read {}", e.path().display()))
+                    .ok()
+            })
+            .collect();
+        if matches.is_empty() {
+            warn!(
+                "Line {} of input list matched no files: {}",
+                number + 1,
+                line
+            );
+        }
+        matches.sort();
+        files.append(&mut matches);
+    }
+    files
+}
+
+/// Parses a sizing of a `--ratios-from` row: `<width>:<height>`, `<width>x<height>`,
+/// `<percent>%` or a lone number taken as `<width>:1`.
+fn parse_sizing(s: &str) -> Option<Sizing> {
+    if let Some(percent) = s.strip_suffix('%') {
+        let percent = percent.parse::<f32>().ok()?;
+        return (percent > 0.0 && percent.is_finite()).then_some(Sizing::Percent(percent));
+    }
+    if s.contains(':') || s.parse::<f32>().is_ok() {
+        let (width, height) = parse_ratio(s)?;
+        return Some(Sizing::AspectRatio(width, height));
+    }
+    let (width, height) = s.split_once('x')?;
+    let (width, height) = (width.parse::<u32>().ok()?, height.parse::<u32>().ok()?);
+    (width > 0 && height > 0).then_some(Sizing::Dimensions(width, height))
+}
+
+/// Parses an aspect ratio, `<width>:<height>` or a lone number taken as `<width>:1`, of
+/// positive finite numbers.
+fn parse_ratio(s: &str) -> Option<(f32, f32)> {
+    let (width, height) = s.split_once(':').unwrap_or((s, "1"));
+    let (width, height) = (width.parse::<f32>().ok()?, height.parse::<f32>().ok()?);
+    let valid = width > 0.0 && height > 0.0 && width.is_finite() && height.is_finite();
+    valid.then_some((width, height))
+}
+
+/// Reads a `--ratios-from` CSV file into a map from file name to sizing. A first row
+/// whose sizing doesn't parse is taken to be a header.
+fn read_sizing_map(path: &PathBuf) -> HashMap<OsString, Sizing> {
+    let contents = fs::read_to_string(path).unwrap_or_else(|_| {
+        error!("Unable to read sizing mapping {}", path.display());
+        exit(exitcode::NOINPUT);
+    });
+    let mut map = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // The sizing never contains a comma, so file names may.
+        let Some((name, value)) = line.rsplit_once(',') else {
+            error!("Line {} of sizing mapping has no sizing.", number + 1);
+            exit(exitcode::DATAERR);
+        };
+        let unquote = |s: &str| {
+            let s = s.trim();
+            s.strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .unwrap_or(s)
+                .to_string()
+        };
+        match parse_sizing(&unquote(value)) {
+            Some(sizing) => {
+                map.insert(OsString::from(unquote(name)), sizing);
+            }
+            None if number == 0 => {}
+            None => {
+                error!(
+                    "Line {} of sizing mapping has an invalid sizing: {}",
+                    number + 1,
+                    value.trim()
+                );
+                exit(exitcode::DATAERR);
+            }
+        }
+    }
+    map
+}
+
+/// Images of a batch with their planned output, or why they have none, and the sizing
+/// each is framed at in place of the batch's, if any.
+type Jobs = (
+    Vec<PathBuf>,
+    Vec<Result<PathBuf, FramerError>>,
+    Vec<Option<Sizing>>,
+);
+
+/// Reads a `--jobs-file` into the inputs it lists, the output of each or why the line
+/// of the input describes no job, and the sizing given on the line, if any.
+fn read_jobs_file(path: &Path) -> Jobs {
+    let contents = fs::read_to_string(path).unwrap_or_else(|_| {
+        error!("Unable to read jobs file {}", path.display());
+        exit(exitcode::NOINPUT);
+    });
+    let mut planned = HashMap::new();
+    let mut jobs = (Vec::new(), Vec::new(), Vec::new());
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut job_sizing = None;
+        let mut fields = line.split('\t').map(str::trim);
+        let input = PathBuf::from(fields.next().unwrap_or_default());
+        let output = match (fields.next(), fields.next(), fields.next()) {
+            (None | Some(""), ..) => Err(FramerError::InvalidJob(format!(
+                "line {} has no output path",
+                number + 1
+            ))),
+            (_, _, Some(_)) => Err(FramerError::InvalidJob(format!(
+                "line {} has more than three columns",
+                number + 1
+            ))),
+            (Some(output), sizing, None) => {
+                let output = PathBuf::from(output);
+                match sizing.filter(|s| !s.is_empty()).map(parse_sizing) {
+                    Some(None) => Err(FramerError::InvalidJob(format!(
+                        "line {} has an invalid sizing",
+                        number + 1
+                    ))),
+                    _ if same_path(&input, &output) => Err(FramerError::OverwritesSource),
+                    sizing => framer::output_format(&output).and_then(|_| {
+                        if let Some(other) = planned.insert(output.clone(), input.clone()) {
+                            planned.insert(output, other.clone());
+                            return Err(FramerError::OutputCollision(other));
+                        }
+                        job_sizing = sizing.flatten();
+                        Ok(output)
+                    }),
+                }
+            }
+        };
+        jobs.0.push(input);
+        jobs.1.push(output);
+        jobs.2.push(job_sizing);
+    }
+    jobs
+}
+
+/// Parses a hex color code such as `#ffffff`, `ffffff` or `#fff`.
+fn parse_color(s: &str) -> Option<Rgb<u8>> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        3 => {
+            let mut channels = hex.chars().map(|c| c.to_digit(16).unwrap() as u8 * 17);
+            Some(Rgb([channels.next()?, channels.next()?, channels.next()?]))
+        }
+        6 => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Some(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+        }
+        _ => None,
+    }
+}
+
+/// Parses a color argument, exiting if it is not a valid hex color code.
+fn color_arg(s: &str, name: &str) -> Rgb<u8> {
+    parse_color(s).unwrap_or_else(|| {
+        error!("{name} is not a valid hex color code.");
+        exit(exitcode::CONFIG);
+    })
+}
+
+/// Parses a `<red>,<green>,<blue>` color argument, exiting if it is invalid.
+fn rgb_arg(s: &str, name: &str) -> Rgb<u8> {
+    let channels: Vec<&str> = s.split(',').collect();
+    let [red, green, blue] = channels[..] else {
+        error!("{name} parameter does not follow expected format.");
+        exit(exitcode::CONFIG);
+    };
+    Rgb([red, green, blue].map(|channel| {
+        channel.trim().parse::<u8>().unwrap_or_else(|_| {
+            error!("{name} value {channel} is not an integer from 0 to 255.");
+            exit(exitcode::CONFIG);
+        })
+    }))
+}
+
+/// Parses a length argument given in pixels or as a percentage below 100%, exiting
+/// if it is invalid.
+fn length_arg(s: &str, name: &str) -> Length {
+    if let Some(percent) = s.strip_suffix('%') {
+        let percent = percent.parse::<f32>().unwrap_or_else(|_| {
+            error!("{name} percentage is not a valid number.");
+            exit(exitcode::CONFIG);
+        });
+        if !(0.0..100.0).contains(&percent) {
+            error!("{name} percentage must be at least 0% and below 100%.");
+            exit(exitcode::CONFIG);
+        }
+        Length::Percent(percent)
+    } else {
+        Length::Pixels(s.parse::<u32>().unwrap_or_else(|_| {
+            error!("{name} is not a valid integer.");
+            exit(exitcode::CONFIG);
+        }))
+    }
+}
+
+/// Parses a margin argument: pixels such as `16` or `16px`, a percentage such as `3%`,
+/// or one of each as `max(16px, 3%)`, exiting if it is invalid.
+fn margin_arg(s: &str) -> Margin {
+    let s = s.trim();
+    let inner = s
+        .strip_prefix("max(")
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(s);
+    let (mut pixels, mut percent) = (None, None);
+    for part in inner.split(',').map(str::trim) {
+        match length_arg(part.strip_suffix("px").unwrap_or(part), "Margin") {
+            Length::Pixels(value) if pixels.is_none() => pixels = Some(value),
+            Length::Percent(value) if percent.is_none() && value < 50.0 => percent = Some(value),
+            Length::Percent(_) if percent.is_none() => {
+                error!("Margin percentage must be below 50%.");
+                exit(exitcode::CONFIG);
+            }
+            _ => {
+                error!("Margin takes at most one length in pixels and one percentage.");
+                exit(exitcode::CONFIG);
+            }
+        }
+    }
+    Margin {
+        pixels: pixels.unwrap_or(0),
+        percent: percent.unwrap_or(0.0),
+    }
+}
+
+/// Describes every argument of the command line as JSON: its name, how it is passed,
+/// whether it takes a value, its default, possible values and help text.
+fn describe_options() -> String {
+    let command = Cli::command();
+    let mut out = String::from("{\"name\":");
+    logging::push_json_string(&mut out, command.get_name());
+    out.push_str(",\"version\":");
+    logging::push_json_string(&mut out, env!("CARGO_PKG_VERSION"));
+    out.push_str(",\"options\":[");
+    let arguments = command
+        .get_arguments()
+        .filter(|arg| !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version));
+    for (i, arg) in arguments.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        logging::push_json_string(&mut out, arg.get_id().as_str());
+        out.push_str(",\"long\":");
+        match arg.get_long() {
+            Some(long) => logging::push_json_string(&mut out, &format!("--{long}")),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"short\":");
+        match arg.get_short() {
+            Some(short) => logging::push_json_string(&mut out, &format!("-{short}")),
+            None => out.push_str("null"),
+        }
+        let possible_values: Vec<String> = arg
+            .get_possible_values()
+            .iter()
+            .map(|value| value.get_name().to_string())
+            .collect();
+        let kind = match arg.get_action() {
+            ArgAction::SetTrue | ArgAction::SetFalse | ArgAction::Count => "flag",
+            _ if !possible_values.is_empty() => "choice",
+            _ => "value",
+        };
+        out.push_str(",\"type\":");
+        logging::push_json_string(&mut out, kind);
+        let _ = write!(
+            out,
+            ",\"positional\":{},\"required\":{}",
+            arg.is_positional(),
+            arg.is_required_set()
+        );
+        out.push_str(",\"value_name\":");
+        match arg.get_value_names().and_then(|names| names.first()) {
+            Some(name) if kind != "flag" => logging::push_json_string(&mut out, name),
+            _ => out.push_str("null"),
+        }
+        out.push_str(",\"default\":");
+        match arg.get_default_values().first() {
+            Some(default) if kind != "flag" => {
+                logging::push_json_string(&mut out, &default.to_string_lossy())
+            }
+            _ => out.push_str("null"),
+        }
+        if kind == "choice" {
+            out.push_str(",\"values\":[");
+            for (i, value) in possible_values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                logging::push_json_string(&mut out, value);
+            }
+            out.push(']');
+        }
+        out.push_str(",\"help\":");
+        match arg.get_long_help().or(arg.get_help()) {
+            Some(help) => logging::push_json_string(&mut out, &help.to_string()),
+            None => out.push_str("null"),
+        }
+        out.push('}');
+    }
+    out.push_str("]}");
+    out
+}
+
+/// Returns whether both directories are on the same filesystem, which is assumed where
+/// it can't be determined.
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(_: &Path, _: &Path) -> bool {
+    true
+}
+
+/// Exits because the single input file is of an unsupported filetype: successfully,
+/// with a notice on stderr whatever the log level, if `skip` is set, and with the error
+/// `code` otherwise.
+fn reject_input(message: &str, code: exitcode::ExitCode, skip: bool) -> ! {
+    if skip {
+        eprintln!("{message} Skipping it.");
+        exit(exitcode::OK);
+    }
+    error!("{message}");
+    exit(code);
+}
+
+/// Describes the version and settings used, for the `--tag-producer` comment.
+fn producer_tag(sizing: Sizing, options: &FramerOptions) -> String {
+    let sizing = match sizing {
+        Sizing::Dimensions(w, h) => format!("dim={w}x{h}"),
+        Sizing::AspectRatio(w, h) => format!("ratio={w}:{h}"),
+        Sizing::Percent(percent) => format!("dim={percent}%"),
+    };
+    let background = match &options.background {
+        Background::Solid(color) => {
+            format!("color=#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+        }
+        Background::Pattern(_) => "background=pattern".to_string(),
+        Background::Pixel(x, y) => format!("background=pixel:{x},{y}"),
+        Background::EdgeMatch => "background=edge-match".to_string(),
+        Background::Blur(sigma) | Background::BlurBars(sigma) => {
+            format!("background=blur:{sigma}")
+        }
+    };
+    format!(
+        "photo_framer v{} {sizing} {background}",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// Reports the outcome of a batch in `format`.
+fn print_summary(counts: &BatchCounts, format: SummaryFormat) {
+    let fields = [
+        ("processed", counts.attempted),
+        ("framed", counts.framed),
+        ("skipped", counts.skipped),
+        ("failed", counts.failed),
+        ("undersized", counts.undersized),
+        ("duplicates", counts.duplicates),
+    ];
+    match format {
+        SummaryFormat::Human => {
+            info!("Framed {} of {} images.", counts.framed, counts.attempted);
+            if counts.undersized > 0 {
+                warn!(
+                    "{} images were smaller than the minimum input size.",
+                    counts.undersized
+                );
+            }
+            if counts.duplicates > 0 {
+                warn!(
+                    "{} images were near duplicates of earlier ones.",
+                    counts.duplicates
+                );
+            }
+        }
+        SummaryFormat::Kv => {
+            let mut line = String::from("RESULT");
+            for (name, count) in fields {
+                let _ = write!(line, " {name}={count}");
+            }
+            println!("{line}");
+        }
+        SummaryFormat::Json => {
+            let mut line = String::from("{");
+            for (i, (name, count)) in fields.into_iter().enumerate() {
+                let _ = write!(line, "{}\"{name}\":{count}", if i > 0 { "," } else { "" });
+            }
+            line.push('}');
+            println!("{line}");
+        }
+    }
+}
+
+/// Reads a plan written by `--plan-out`.
+fn read_plan(path: &Path) -> Result<Plan, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read plan {}: {e}", path.display()))?;
+    Plan::parse(&json).ok_or_else(|| format!("{} is not a valid plan.", path.display()))
+}
 
-    let cli = Cli::parse();
+/// The arguments of this run, without the program name, `option` and its value.
+fn arguments_without(option: &str) -> Vec<String> {
+    let mut arguments = Vec::new();
+    let mut args = env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned());
+    while let Some(arg) = args.next() {
+        if arg == option {
+            args.next();
+        } else if !arg
+            .strip_prefix(option)
+            .is_some_and(|rest| rest.starts_with('='))
+        {
+            arguments.push(arg);
+        }
+    }
+    arguments
+}
+
+/// The arguments of this run without `--plan-out` and its value, to be recorded in the plan,
+/// with the paths they give expanded and made absolute, so a plan replays the same from
+/// any directory and environment.
+fn plan_arguments() -> Vec<String> {
+    let command = Cli::command();
+    // `frame` and `convert` take the same arguments as no command at all.
+    let paths: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| arg.get_value_parser().type_id() == TypeId::of::<PathBuf>())
+        .collect();
+    let takes_path = |flag: &str| {
+        paths.iter().any(|arg| match flag.strip_prefix("--") {
+            Some(long) => arg.get_long() == Some(long),
+            None => flag
+                .strip_prefix('-')
+                .and_then(|short| short.parse().ok())
+                .is_some_and(|short| arg.get_short() == Some(short)),
+        })
+    };
+    let absolute = |value: &str| {
+        expand_path(value)
+            .ok()
+            .and_then(|path| std::path::absolute(path).ok())
+            .map_or_else(
+                || value.to_string(),
+                |path| path.to_string_lossy().into_owned(),
+            )
+    };
+    let mut arguments = Vec::new();
+    let mut args = arguments_without("--plan-out").into_iter();
+    while let Some(arg) = args.next() {
+        match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with('-') && takes_path(flag) => {
+                arguments.push(format!("{flag}={}", absolute(value)));
+            }
+            None if takes_path(&arg) => {
+                let value = args.next();
+                arguments.push(arg);
+                arguments.extend(value.as_deref().map(absolute));
+            }
+            _ => arguments.push(arg),
+        }
+    }
+    arguments
+}
+
+/// The files of a folder or input list with the directory their outputs are written
+/// into, or `None` for a single input image.
+fn batch_files<'a>(
+    input_list: &Option<PathBuf>,
+    input: &'a Option<PathBuf>,
+    output: Option<&'a Path>,
+) -> Option<(Vec<PathBuf>, Option<&'a Path>)> {
+    match (input_list, input) {
+        (Some(list), _) => Some((read_input_list(list), output)),
+        (None, Some(input)) if input.is_dir() => Some((
+            directory_files(input).unwrap_or_else(|e| {
+                error!("Unable to read {}: {e}", input.display());
+                exit(exitcode::NOINPUT);
+            }),
+            Some(output.unwrap_or(input)),
+        )),
+        _ => None,
+    }
+}
+
+/// Asks whether to overwrite the files among `outputs` that already exist, exiting
+/// unless the answer is yes.
+fn confirm_overwrites(outputs: &[PathBuf]) {
+    let existing = outputs.iter().filter(|output| output.exists()).count();
+    if existing == 0 {
+        return;
+    }
+    eprint!(
+        "{existing} of {} outputs already exist and would be overwritten. Continue? [y/N] ",
+        outputs.len()
+    );
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err()
+        || !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+    {
+        error!("Cancelled without framing anything.");
+        exit(exitcode::NOPERM);
+    }
+}
+
+fn main() {
+    let mut cli = Cli::parse().into_frame_args();
+    // A plan replaces the arguments with those it was written with, followed by those
+    // given along with it, which override them.
+    let plan = cli.from_plan.as_deref().map(read_plan);
+    if let Some(Ok(plan)) = &plan {
+        let given = arguments_without("--from-plan");
+        // The command is that of the plan.
+        let command_name = given
+            .first()
+            .is_some_and(|arg| arg == "frame" || arg == "convert");
+        let given = given.iter().skip(usize::from(command_name));
+        let mut command = Cli::command().args_override_self(true);
+        for name in ["frame", "convert"] {
+            command = command.mut_subcommand(name, |command| command.args_override_self(true));
+        }
+        let matches = command.get_matches_from(
+            iter::once(&String::from("photo_framer"))
+                .chain(&plan.arguments)
+                .chain(given),
+        );
+        cli = Cli::from_arg_matches(&matches)
+            .unwrap_or_else(|e| e.exit())
+            .into_frame_args();
+    }
+    if cli.describe_options {
+        println!("{}", describe_options());
+        return;
+    }
+    let level = match cli.verbose {
+        0 => None,
+        1 => Some(log::LevelFilter::Info),
+        _ => Some(log::LevelFilter::Debug),
+    };
+    logging::init(cli.log_format, level);
+    let plan = plan.map(|plan| {
+        plan.unwrap_or_else(|message| {
+            error!("{message}");
+            exit(exitcode::NOINPUT);
+        })
+    });
 
     // Validating sizing parameter, making sure only one of either ratio or dimension
     // is provided and that they are formatted correctly.
     let sizing = match (cli.aspect_ratio, cli.dimensions) {
+        (None, Some(s)) if s.ends_with('%') => {
+            let percent = s.trim_end_matches('%').parse::<f32>().unwrap_or_else(|_| {
+                error!("Output image dimension percentage is not a valid number.");
+                exit(exitcode::CONFIG);
+            });
+            if percent <= 0.0 || !percent.is_finite() {
+                error!("Output image dimension percentage must be positive.");
+                exit(exitcode::CONFIG);
+            }
+            Sizing::Percent(percent)
+        }
         (None, Some(s)) => {
             let parts = s.split_once('x').unwrap_or_else(|| {
                 error!("Output image dimension parameter does not follow expected format.");
@@ -63,96 +1487,645 @@ fn main() {
             Sizing::Dimensions(width, height)
         }
         (Some(s), None) => {
-            let parts = s.split_once(':').unwrap_or_else(|| {
-                error!("Output image dimension parameter does not follow expected format.");
-                exit(exitcode::CONFIG);
-            });
-            let width = parts.0.parse::<f32>().unwrap_or_else(|_| {
-                error!("Output image dimension width is not a valid integer.");
+            let (width, height) = parse_ratio(&s).unwrap_or_else(|| {
+                error!("Aspect ratio must be two positive numbers as <width>:<height>, or one.");
                 exit(exitcode::CONFIG);
             });
-            let height = parts.1.parse::<f32>().unwrap_or_else(|_| {
-                error!("Output image dimension height is not a valid integer.");
-                exit(exitcode::CONFIG);
-            });
-            Sizing::AspectRatio(width, height)
+            if cli.snap_ratio {
+                if let Some((w, h)) = framer::snap_ratio(width, height) {
+                    warn!("Snapped aspect ratio {width}:{height} to {w}:{h}.");
+                    Sizing::AspectRatio(w, h)
+                } else {
+                    warn!("Aspect ratio {width}:{height} is not close to a standard ratio.");
+                    Sizing::AspectRatio(width, height)
+                }
+            } else {
+                Sizing::AspectRatio(width, height)
+            }
         }
         (Some(_), Some(_)) => {
             error!("Either the aspect ratio or the dimensions can be provided, but not both.");
             exit(exitcode::CONFIG);
         }
+        (None, None) if cli.story => Sizing::AspectRatio(9.0, 16.0),
+        (None, None) if cli.convert_only => Sizing::Percent(100.0),
+        // Never used, as images without a row of their own are skipped.
+        (None, None) if cli.require_mapping => Sizing::Percent(100.0),
         (None, None) => {
             error!("An aspect ratio or output image dimension must be provided.");
             exit(exitcode::CONFIG);
         }
     };
-    if !cli.output.exists() || !cli.output.is_dir() {
-        error!("The output directory does not exist.");
-        exit(exitcode::IOERR);
-    }
-    if let Ok(dir_files) = cli.input.read_dir() {
-        for file in dir_files {
-            if file.is_err() {
-                continue;
-            }
-            let file = file.unwrap().path();
-            if file.extension().is_some_and(|ext| {
-                !accepted_extensions.contains(&ext.display().to_string().as_str())
-            }) {
-                continue;
+    let mut options = FramerOptions {
+        background: Background::Solid(match &cli.background_rgb {
+            Some(s) => rgb_arg(s, "Border RGB color"),
+            None => color_arg(&cli.color, "Border color"),
+        }),
+        landscape_color: cli
+            .color_landscape
+            .as_deref()
+            .map(|s| color_arg(s, "Landscape border color")),
+        portrait_color: cli
+            .color_portrait
+            .as_deref()
+            .map(|s| color_arg(s, "Portrait border color")),
+        match_mode: cli.match_mode.into(),
+        flatten_color: cli
+            .flatten_color
+            .as_deref()
+            .map(|s| color_arg(s, "Flatten color")),
+        filter: cli.filter.into(),
+        upscale_filter: cli.upscale_filter.map(Into::into),
+        downscale_filter: cli.downscale_filter.map(Into::into),
+        background_filter: cli.background_filter.into(),
+        small_image: cli.small_image.into(),
+        no_resize: cli.no_resize,
+        overflow: cli.overflow.into(),
+        orientation: cli.orientation.into(),
+        frame: match cli.frame.as_str() {
+            "first" => AnimationFrame::First,
+            "middle" => AnimationFrame::Middle,
+            "last" => AnimationFrame::Last,
+            index => AnimationFrame::Index(index.parse().unwrap_or_else(|_| {
+                error!("Frame must be `first`, `middle`, `last` or a frame index.");
+                exit(exitcode::CONFIG);
+            })),
+        },
+        suffix: cli.suffix.clone().filter(|suffix| !suffix.is_empty()),
+        output_extension: cli.output_filetype.map(|filetype| {
+            match filetype {
+                OutputType::Jpeg => "jpeg",
+                OutputType::Png => "png",
+                OutputType::Webp => "webp",
             }
-            let mut output = cli.output.clone();
-            let filename = file.file_name();
-            if filename.is_none() {
-                continue;
+            .to_string()
+        }),
+        extension_filter: !cli.no_extension_filter,
+        sort: cli.sort.map(Into::into),
+        sort_descending: cli.sort_desc,
+        flatten_output: cli.flatten_output,
+        uniform_size: cli.uniform_size,
+        comparison: cli.comparison,
+        verify: cli.verify,
+        dedup: cli.dedup.map(Into::into),
+        dedup_threshold: cli.dedup_threshold,
+        delete_unverified: cli.delete_unverified,
+        jobs: cli.jobs as usize,
+        ..Default::default()
+    };
+    if cli.uniform_size && matches!(sizing, Sizing::Percent(_)) {
+        error!("A uniform size requires an aspect ratio or dimensions.");
+        exit(exitcode::CONFIG);
+    }
+    if let Some(s) = &cli.min_input_size {
+        let parts = s.split_once('x').unwrap_or_else(|| {
+            error!("Minimum input size parameter does not follow expected format.");
+            exit(exitcode::CONFIG);
+        });
+        let width = parts.0.parse::<u32>().unwrap_or_else(|_| {
+            error!("Minimum input width is not a valid integer.");
+            exit(exitcode::CONFIG);
+        });
+        let height = parts.1.parse::<u32>().unwrap_or_else(|_| {
+            error!("Minimum input height is not a valid integer.");
+            exit(exitcode::CONFIG);
+        });
+        options.min_input_size = Some((width, height));
+        options.strict_min_size = cli.strict_min_size;
+    }
+    if let Some(s) = &cli.crop {
+        let parts: Vec<&str> = s.split(',').collect();
+        let [x, y, width, height] = parts[..] else {
+            error!("Crop parameter does not follow expected format.");
+            exit(exitcode::CONFIG);
+        };
+        let [x, y, width, height] = [x, y, width, height].map(|part| {
+            part.trim().parse::<u32>().unwrap_or_else(|_| {
+                error!("Crop rectangle value {part} is not a valid integer.");
+                exit(exitcode::CONFIG);
+            })
+        });
+        if width == 0 || height == 0 {
+            error!("Crop width and height must be positive.");
+            exit(exitcode::CONFIG);
+        }
+        options.crop = Some((x, y, width, height));
+    }
+    if let Some(path) = &cli.ratios_from {
+        options.sizing_overrides = read_sizing_map(path);
+        options.require_sizing_override = cli.require_mapping;
+    }
+    if cli.snap_threshold < 0.0 || !cli.snap_threshold.is_finite() {
+        error!("Snap threshold must be a non-negative number.");
+        exit(exitcode::CONFIG);
+    }
+    options.snap_threshold = cli.snap_threshold;
+    options.max_aspect = cli.max_aspect.as_deref().map(|s| {
+        let parts = s.split_once(':').unwrap_or((s, "1"));
+        match (parts.0.parse::<f32>(), parts.1.parse::<f32>()) {
+            (Ok(w), Ok(h)) if w > 0.0 && h > 0.0 && (w / h).is_finite() => (w / h).max(h / w),
+            _ => {
+                error!("Maximum aspect ratio must be positive and given as `<width>:<height>`.");
+                exit(exitcode::CONFIG);
             }
-            let filename = filename.unwrap();
-            output.push(filename);
-            if let Some(filetype) = cli.output_filetype {
-                match filetype {
-                    OutputType::Jpeg => output.set_extension("jpeg"),
-                    OutputType::Png => output.set_extension("png"),
-                    OutputType::Webp => output.set_extension("webp"),
-                };
-            };
-            if frame_image(&file, &output, sizing).is_err() {
-                error!("Failed to frame image {}", file.display().to_string());
+        }
+    });
+    if cli.pad_to_multiple == Some(0) {
+        error!("Padding multiple must be a positive integer.");
+        exit(exitcode::CONFIG);
+    }
+    options.pad_to_multiple = cli.pad_to_multiple;
+    if cli.min_dimension == Some(0) {
+        error!("Minimum dimension must be a positive integer.");
+        exit(exitcode::CONFIG);
+    }
+    options.min_dimension = cli.min_dimension;
+    if !(cli.fill_ratio > 0.0 && cli.fill_ratio <= 1.0) {
+        error!("Fill ratio must be above 0 and at most 1.");
+        exit(exitcode::CONFIG);
+    }
+    if cli.fill_ratio < 1.0 && matches!(sizing, Sizing::Percent(_)) {
+        error!("A fill ratio requires an aspect ratio or dimensions.");
+        exit(exitcode::CONFIG);
+    }
+    options.fill_ratio = cli.fill_ratio;
+    options.margin = cli.margin.as_deref().map(margin_arg);
+    if let Some(s) = &cli.multi_up {
+        let parts = s.split_once('x').unwrap_or_else(|| {
+            error!("Multi-up parameter does not follow expected format.");
+            exit(exitcode::CONFIG);
+        });
+        let columns = parts.0.parse::<u32>().unwrap_or_else(|_| {
+            error!("Multi-up columns is not a valid integer.");
+            exit(exitcode::CONFIG);
+        });
+        let rows = parts.1.parse::<u32>().unwrap_or_else(|_| {
+            error!("Multi-up rows is not a valid integer.");
+            exit(exitcode::CONFIG);
+        });
+        if columns == 0 || rows == 0 || cli.sheet_dpi == 0 {
+            error!("Multi-up columns, rows and sheet DPI must be positive.");
+            exit(exitcode::CONFIG);
+        }
+        let (width, height) = cli.sheet.inches();
+        let (width, height) = if columns > rows {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let dpi = cli.sheet_dpi as f32;
+        let (width, height) = ((width * dpi).round() as u32, (height * dpi).round() as u32);
+        if columns > width || rows > height {
+            error!("The sheet is too small for {columns}x{rows} copies.");
+            exit(exitcode::CONFIG);
+        }
+        options.sheet = Some(Sheet {
+            width,
+            height,
+            columns,
+            rows,
+        });
+    }
+    if cli.fast {
+        options.filter = ResizeFilter::Convolution(FilterType::Triangle);
+        options.box_prescale = true;
+    }
+    if let Some(path) = &cli.background_pattern {
+        if cli.pattern_scale <= 0.0 || !cli.pattern_scale.is_finite() {
+            error!("Pattern scale must be a positive number.");
+            exit(exitcode::CONFIG);
+        }
+        let offset = match &cli.pattern_offset {
+            None => (0, 0),
+            Some(s) => {
+                let parts = s.split_once(',').unwrap_or_else(|| {
+                    error!("Pattern offset parameter does not follow expected format.");
+                    exit(exitcode::CONFIG);
+                });
+                let x = parts.0.parse::<u32>().unwrap_or_else(|_| {
+                    error!("Pattern offset x is not a valid integer.");
+                    exit(exitcode::CONFIG);
+                });
+                let y = parts.1.parse::<u32>().unwrap_or_else(|_| {
+                    error!("Pattern offset y is not a valid integer.");
+                    exit(exitcode::CONFIG);
+                });
+                (x, y)
             }
+        };
+        let pattern = Pattern::load(path, cli.pattern_scale, offset).unwrap_or_else(|_| {
+            error!("Failed to load background pattern {}", path.display());
+            exit(exitcode::NOINPUT);
+        });
+        options.background = Background::Pattern(pattern);
+    }
+    if let Some(sigma) = cli.background_blur.or(cli.story.then_some(STORY_BLUR)) {
+        if sigma <= 0.0 || !sigma.is_finite() {
+            error!("Background blur must be a positive number.");
+            exit(exitcode::CONFIG);
+        }
+        options.background = if cli.blur_bars {
+            Background::BlurBars(sigma)
+        } else {
+            Background::Blur(sigma)
+        };
+    } else if cli.blur_bars {
+        error!("Blurring the bars requires `--background-blur` or `--story`.");
+        exit(exitcode::CONFIG);
+    }
+    if let Some(s) = &cli.background_pixel {
+        let parts = s.split_once(',').unwrap_or_else(|| {
+            error!("Background pixel parameter does not follow expected format.");
+            exit(exitcode::CONFIG);
+        });
+        let x = parts.0.parse::<u32>().unwrap_or_else(|_| {
+            error!("Background pixel x is not a valid integer.");
+            exit(exitcode::CONFIG);
+        });
+        let y = parts.1.parse::<u32>().unwrap_or_else(|_| {
+            error!("Background pixel y is not a valid integer.");
+            exit(exitcode::CONFIG);
+        });
+        options.background = Background::Pixel(x, y);
+    }
+    if cli.background_edge_match {
+        options.background = Background::EdgeMatch;
+    }
+    if let Some(s) = &cli.caption_space {
+        options.caption_space = Some(length_arg(s, "Caption space"));
+    }
+    if let Some(s) = &cli.brand_bar {
+        let logo = cli.brand_logo.as_ref().map(|path| {
+            BrandBar::load_logo(path).unwrap_or_else(|_| {
+                error!("Unable to load brand logo {}", path.display());
+                exit(exitcode::NOINPUT);
+            })
+        });
+        options.brand_bar = Some(BrandBar {
+            height: length_arg(s, "Brand bar height"),
+            position: cli.brand_position.into(),
+            color: color_arg(&cli.brand_color, "Brand bar color"),
+            text: cli.brand_text.clone(),
+            text_color: color_arg(&cli.brand_text_color, "Brand text color"),
+            logo,
+        });
+    }
+    if let Some(path) = &cli.lut {
+        options.lut = Some(lut::Lut::load(path).unwrap_or_else(|e| {
+            error!("Unable to load LUT {}: {e}", path.display());
+            exit(exitcode::DATAERR);
+        }));
+        options.lut_background = cli.lut_background;
+    }
+    options.date_stamp = cli.date_stamp.map(|source| DateStamp {
+        source: source.into(),
+        format: cli.date_format.clone(),
+        color: color_arg(&cli.date_color, "Date stamp color"),
+    });
+    if let Some(s) = &cli.edge_stroke {
+        let parts = s.split_once(',').unwrap_or_else(|| {
+            error!("Edge stroke parameter does not follow expected format.");
+            exit(exitcode::CONFIG);
+        });
+        let width = parts.0.parse::<u32>().unwrap_or_else(|_| {
+            error!("Edge stroke width is not a valid integer.");
+            exit(exitcode::CONFIG);
+        });
+        let color = color_arg(parts.1, "Edge stroke color");
+        options.edge_stroke = (width > 0).then_some((width, color));
+    }
+    options.embed_thumbnail = cli.embed_thumbnail;
+    if cli.tag_producer {
+        options.comment = Some(producer_tag(sizing, &options));
+    }
+    if let Some(output) = &cli.output
+        && !output.exists()
+        && cli.create_output_dir
+        && let Err(e) = fs::create_dir_all(output)
+    {
+        error!("Unable to create the output directory: {e}");
+        exit(exitcode::CANTCREAT);
+    }
+    if let Some(output) = &cli.output
+        && (!output.exists() || !output.is_dir())
+    {
+        error!("The output directory does not exist.");
+        exit(exitcode::IOERR);
+    }
+    let in_place = match (&cli.output, &cli.input) {
+        // Jobs name each output themselves.
+        (None, _) if cli.jobs_file.is_some() => false,
+        (None, _) => true,
+        (Some(output), Some(input)) if input.is_dir() => same_path(output, input),
+        (Some(output), Some(input)) => same_path(output, parent_dir(input)),
+        (Some(_), None) => false,
+    };
+    if in_place && options.suffix.is_none() {
+        error!(
+            "Writing into the input directory requires `--suffix` to avoid overwriting the originals."
+        );
+        exit(exitcode::CONFIG);
+    }
+    if let Some(plan_out) = &cli.plan_out {
+        let (files, output) = batch_files(&cli.input_list, &cli.input, cli.output.as_deref())
+            .unwrap_or_else(|| {
+                error!("Plans are only written for folders and input lists.");
+                exit(exitcode::USAGE);
+            });
+        let (files, outputs) = plan_files(files, output, &options);
+        // Absolute like the arguments, so the plan can be replayed from anywhere.
+        let absolute = |path: PathBuf| std::path::absolute(&path).unwrap_or(path);
+        let plan = Plan {
+            arguments: plan_arguments(),
+            files: files
+                .into_iter()
+                .map(absolute)
+                .zip(
+                    outputs
+                        .into_iter()
+                        .map(|output| output.map(absolute).map_err(|e| e.to_string())),
+                )
+                .collect(),
+        };
+        fs::write(plan_out, plan.to_json()).unwrap_or_else(|e| {
+            error!("Unable to write plan {}: {e}", plan_out.display());
+            exit(exitcode::CANTCREAT);
+        });
+        info!(
+            "Planned {} images into {}.",
+            plan.files.len(),
+            plan_out.display()
+        );
+        return;
+    }
+    // Held until the process exits. Without `--output`, an input list may write into
+    // any number of directories, so nothing is locked.
+    let lock_dir = match (&cli.output, &cli.input) {
+        (Some(output), _) => Some(output.as_path()),
+        (None, Some(input)) if input.is_dir() => Some(input.as_path()),
+        (None, Some(input)) if cli.input_list.is_none() => Some(parent_dir(input)),
+        _ => None,
+    };
+    let _lock = lock_dir.filter(|_| !cli.no_lock).and_then(lock_output_dir);
+    if let Some(temp_dir) = &cli.temp_dir {
+        if !temp_dir.is_dir() {
+            error!("The temporary directory does not exist.");
+            exit(exitcode::IOERR);
+        }
+        if let Some(dir) = lock_dir
+            && !same_filesystem(temp_dir, dir)
+        {
+            warn!(
+                "{} is on another filesystem than {}, so outputs are copied into place instead of being renamed atomically.",
+                temp_dir.display(),
+                dir.display()
+            );
+        }
+        options.temp_dir = Some(temp_dir.clone());
+    }
+    // Plans and jobs files give the output of each image themselves. Folders and input
+    // lists are planned here, once, so overwrites are confirmed for the outputs written.
+    let planned: Option<Jobs> = match (plan, &cli.jobs_file) {
+        (Some(plan), _) => {
+            let (files, outputs): (Vec<PathBuf>, Vec<_>) = plan
+                .files
+                .into_iter()
+                .map(|(file, output)| (file, output.map_err(FramerError::Skipped)))
+                .unzip();
+            let sizings = vec![None; files.len()];
+            Some((files, outputs, sizings))
+        }
+        (None, Some(path)) => Some(read_jobs_file(path)),
+        (None, None) if cli.streaming => None,
+        (None, None) => batch_files(&cli.input_list, &cli.input, cli.output.as_deref()).map(
+            |(files, output)| {
+                let (files, outputs) = plan_files(files, output, &options);
+                let sizings = vec![None; files.len()];
+                (files, outputs, sizings)
+            },
+        ),
+    };
+    // Streamed folders aren't listed up front, so there is nothing to ask about.
+    if let Some((_, outputs, _)) = &planned
+        && !cli.yes
+        && io::stdin().is_terminal()
+    {
+        let outputs: Vec<PathBuf> = outputs
+            .iter()
+            .filter_map(|output| output.as_ref().ok().cloned())
+            .collect();
+        confirm_overwrites(&outputs);
+    }
+    let counts = match (planned, &cli.input) {
+        (Some((files, outputs, sizings)), _) => Some(BatchCounts::of(&frame_planned(
+            files, outputs, sizings, sizing, &options,
+        ))),
+        (None, Some(input)) if input.is_dir() && cli.streaming => Some(stream_directory(
+            input,
+            cli.output.as_deref().unwrap_or(input),
+            sizing,
+            &options,
+        )),
+        _ => None,
+    };
+    if let Some(counts) = counts {
+        print_summary(&counts, cli.summary_format);
+        if counts.attempted == 0 && cli.fail_on_empty {
+            error!("No images with an accepted filetype were found.");
+            exit(exitcode::NOINPUT);
+        } else if counts.attempted == 0 {
+            // Printed whatever the log level, as nothing else tells that nothing was framed.
+            eprintln!("No images with an accepted filetype were found.");
         }
     } else {
         // This assumes the input path leads to a single image.
-        let filename = &cli.input.file_name().unwrap_or_else(|| {
+        let input = cli.input.unwrap();
+        if input.file_name().is_none() {
             error!("Unable to find input file.");
             exit(exitcode::CONFIG);
-        });
-        if !accepted_extensions.contains(
-            &cli.input
-                .extension()
-                .unwrap_or_else(|| {
-                    error!("Unable to detect input file's filetype.");
-                    exit(exitcode::DATAERR);
-                })
-                .display()
-                .to_string()
-                .as_str(),
-        ) {
+        }
+        if let Err(e) = framer::check_support(&input) {
+            reject_input(
+                &format!("Unable to frame {}: {e}.", input.display()),
+                exitcode::CONFIG,
+                cli.skip_unsupported,
+            );
+        }
+        if !cli.no_extension_filter {
+            if input.extension().is_none() {
+                reject_input(
+                    "Unable to detect input file's filetype.",
+                    exitcode::DATAERR,
+                    cli.skip_unsupported,
+                );
+            }
+            if !framer::has_accepted_extension(&input) {
+                let raw = if cfg!(feature = "raw") {
+                    ", or camera RAW"
+                } else {
+                    ""
+                };
+                reject_input(
+                    &format!(
+                        "Input file's filetype is unsupported. Use only `gif`, `jpeg`, `jpg`, `png`, or `webp`{raw} files."
+                    ),
+                    exitcode::CONFIG,
+                    cli.skip_unsupported,
+                );
+            }
+        }
+        let output_dir = cli.output.as_deref().unwrap_or(parent_dir(&input));
+        let output = output_path(output_dir, &input, &options).unwrap();
+        if let Err(e) = framer::output_format(&output) {
             error!(
-                "Input file's filetype is unsupported. Use only `jpeg`, `jpg`, `png`, or `webp` files."
+                "Unable to write {}: {e}. Give an output filetype.",
+                output.display()
             );
             exit(exitcode::CONFIG);
         }
-        let mut output = cli.output;
-        output.push(filename);
-        if let Some(filetype) = cli.output_filetype {
-            match filetype {
-                OutputType::Jpeg => output.set_extension("jpeg"),
-                OutputType::Png => output.set_extension("png"),
-                OutputType::Webp => output.set_extension("webp"),
-            };
-        };
-        if frame_image(&cli.input, &output, sizing).is_err() {
-            error!("Failed to frame image.");
+        if same_path(&output, &input) {
+            error!("Refusing to overwrite source image.");
             exit(exitcode::CANTCREAT);
         }
+        match frame_image(&input, &output, sizing, &options) {
+            Ok(_) => {}
+            Err(FramerError::Skipped(reason)) => warn!("Skipped image: {reason}"),
+            Err(e) => {
+                error!("Failed to frame image: {e}");
+                exit(exitcode::CANTCREAT);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(s: &str) -> String {
+        expand_path(s).unwrap().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn expand_path_expands_home_and_variables() {
+        let home = env::var("HOME").unwrap();
+        assert_eq!(expanded("~/photos"), format!("{home}/photos"));
+        assert_eq!(expanded("$HOME/a.png"), format!("{home}/a.png"));
+        assert_eq!(expanded("${HOME}x.png"), format!("{home}x.png"));
+        assert_eq!(expanded("a~/b"), "a~/b");
+    }
+
+    #[test]
+    fn expand_path_keeps_unexpandable_dollars() {
+        assert_eq!(expanded("photo$1.png"), "photo$1.png");
+        assert_eq!(
+            expanded("$PHOTO_FRAMER_UNSET_TEST_VAR.png"),
+            "$PHOTO_FRAMER_UNSET_TEST_VAR.png"
+        );
+        assert_eq!(
+            expanded("${PHOTO_FRAMER_UNSET_TEST_VAR}.png"),
+            "${PHOTO_FRAMER_UNSET_TEST_VAR}.png"
+        );
+        assert_eq!(expanded("a${b.png"), "a${b.png");
+        assert_eq!(expanded("${}.png"), "${}.png");
+        assert_eq!(expanded("cost$"), "cost$");
+        assert_eq!(expanded("$ a"), "$ a");
+    }
+
+    #[test]
+    fn expand_path_escapes_double_dollars() {
+        assert_eq!(expanded("$$HOME.png"), "$HOME.png");
+        assert_eq!(expanded("a$$$$b"), "a$$b");
+        assert_eq!(expanded("$${HOME}"), "${HOME}");
+    }
+
+    #[test]
+    fn parse_ratio_takes_a_lone_number_as_width() {
+        assert_eq!(parse_ratio("1.5"), Some((1.5, 1.0)));
+        assert_eq!(parse_ratio("0.8"), Some((0.8, 1.0)));
+        assert_eq!(parse_ratio("3:2"), Some((3.0, 2.0)));
+        for invalid in ["2x3", "0", "-1.5", "3:0", "1:inf", "a:b", ""] {
+            assert_eq!(parse_ratio(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_sizing_keeps_lone_numbers_apart_from_dimensions() {
+        assert!(matches!(
+            parse_sizing("1.5"),
+            Some(Sizing::AspectRatio(1.5, 1.0))
+        ));
+        assert!(matches!(
+            parse_sizing("3:2"),
+            Some(Sizing::AspectRatio(3.0, 2.0))
+        ));
+        assert!(matches!(
+            parse_sizing("2x3"),
+            Some(Sizing::Dimensions(2, 3))
+        ));
+        assert!(matches!(parse_sizing("50%"), Some(Sizing::Percent(50.0))));
+        assert!(parse_sizing("0").is_none());
+    }
+
+    #[test]
+    fn lone_number_ratios_size_the_canvas() {
+        for (ratio, canvas) in [("1.5", (300, 200)), ("0.8", (300, 375)), ("2", (400, 200))] {
+            let (w, h) = parse_ratio(ratio).unwrap();
+            let img = image::DynamicImage::ImageRgb8(image::RgbImage::new(300, 200));
+            let framed =
+                framer::frame_image_buffer(img, Sizing::AspectRatio(w, h), &Default::default())
+                    .unwrap();
+            assert_eq!((framed.width, framed.height), canvas, "{ratio}");
+        }
+    }
+
+    #[test]
+    fn jobs_file_lines_without_a_job_are_invalid() {
+        let path = env::temp_dir().join(format!("photo_framer_{}_jobs.tsv", std::process::id()));
+        fs::write(&path, "a.png\na.png\tb.png\tbogus\na.png\tb.png\t3:2\tx\n").unwrap();
+        let (inputs, outputs, _) = read_jobs_file(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(inputs.len(), 3);
+        for (output, cause) in outputs
+            .iter()
+            .zip(["no output", "invalid sizing", "columns"])
+        {
+            assert!(
+                matches!(output, Err(FramerError::InvalidJob(message)) if message.contains(cause)),
+                "{cause}"
+            );
+        }
+    }
+
+    #[test]
+    fn background_rgb_conflicts_with_other_backgrounds() {
+        for other in [
+            &["--background-pattern", "tile.png"][..],
+            &["--background-blur", "20"],
+            &["--story"],
+            &["--background-pixel", "0,0"],
+            &["--background-edge-match"],
+        ] {
+            let args = [&["photo_framer", "--background-rgb", "30,30,30"][..], other].concat();
+            let error = Cli::try_parse_from(args).err();
+            assert_eq!(
+                error.map(|e| e.kind()),
+                Some(ErrorKind::ArgumentConflict),
+                "{other:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn bare_dedup_leaves_the_output_filetype_alone() {
+        let cli = Cli::try_parse_from(["photo_framer", "-i", "in", "--dedup", "png"])
+            .unwrap()
+            .into_frame_args();
+        assert!(matches!(cli.dedup, Some(Dedup::Flag)));
+        assert!(matches!(cli.output_filetype, Some(OutputType::Png)));
+        let cli = Cli::try_parse_from(["photo_framer", "-i", "in", "--dedup=skip"])
+            .unwrap()
+            .into_frame_args();
+        assert!(matches!(cli.dedup, Some(Dedup::Skip)));
     }
 }
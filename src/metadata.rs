@@ -0,0 +1,125 @@
+use image::ImageFormat;
+
+/// Inserts a text comment into an encoded image. JPEG images receive a `COM`
+/// segment and PNG images a `tEXt` chunk. Returns `None` if `format` has no
+/// supported comment field.
+pub fn insert_comment(encoded: &[u8], format: ImageFormat, comment: &str) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Jpeg => insert_jpeg_comment(encoded, comment),
+        ImageFormat::Png => insert_png_comment(encoded, comment),
+        _ => None,
+    }
+}
+
+fn insert_jpeg_comment(encoded: &[u8], comment: &str) -> Option<Vec<u8>> {
+    // The segment length includes its own two bytes.
+    let comment = &comment.as_bytes()[..comment.len().min(u16::MAX as usize - 2)];
+    // Skip SOI and any APPn segments, which must come first.
+    let mut position = 2;
+    while encoded.get(position) == Some(&0xFF)
+        && encoded
+            .get(position + 1)
+            .is_some_and(|marker| (0xE0..=0xEF).contains(marker))
+    {
+        let length = u16::from_be_bytes([*encoded.get(position + 2)?, *encoded.get(position + 3)?]);
+        position += 2 + length as usize;
+    }
+    if position > encoded.len() {
+        return None;
+    }
+    let mut tagged = Vec::with_capacity(encoded.len() + comment.len() + 4);
+    tagged.extend_from_slice(&encoded[..position]);
+    tagged.extend_from_slice(&[0xFF, 0xFE]);
+    tagged.extend_from_slice(&(comment.len() as u16 + 2).to_be_bytes());
+    tagged.extend_from_slice(comment);
+    tagged.extend_from_slice(&encoded[position..]);
+    Some(tagged)
+}
+
+/// Inserts an EXIF `APP1` segment holding `thumbnail`, a JPEG preview, into an
+/// encoded JPEG image. Returns `None` if the thumbnail doesn't fit in a segment.
+pub fn insert_jpeg_thumbnail(encoded: &[u8], thumbnail: &[u8]) -> Option<Vec<u8>> {
+    // A big-endian TIFF header, then IFD0 with only the orientation, then IFD1 pointing
+    // at the thumbnail that follows it.
+    let mut tiff = b"MM\0\x2A".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes());
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    ifd_entry(&mut tiff, 0x0112, SHORT, 1);
+    let ifd1 = tiff.len() as u32 + 4;
+    tiff.extend_from_slice(&ifd1.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    let data = ifd1 + 2 + 3 * 12 + 4;
+    // Compression 6 is a JPEG thumbnail.
+    ifd_entry(&mut tiff, 0x0103, SHORT, 6);
+    ifd_entry(&mut tiff, 0x0201, LONG, data);
+    ifd_entry(&mut tiff, 0x0202, LONG, thumbnail.len() as u32);
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff.extend_from_slice(thumbnail);
+    // The segment length includes its own two bytes and the `Exif` identifier.
+    let length = u16::try_from(tiff.len() + 8).ok()?;
+    // EXIF belongs right after SOI, but after a JFIF `APP0` segment where there is one.
+    let mut position = 2;
+    if encoded.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    if encoded.get(2..4) == Some(&[0xFF, 0xE0]) {
+        position += 2 + u16::from_be_bytes([*encoded.get(4)?, *encoded.get(5)?]) as usize;
+    }
+    let mut tagged = Vec::with_capacity(encoded.len() + tiff.len() + 10);
+    tagged.extend_from_slice(encoded.get(..position)?);
+    tagged.extend_from_slice(&[0xFF, 0xE1]);
+    tagged.extend_from_slice(&length.to_be_bytes());
+    tagged.extend_from_slice(b"Exif\0\0");
+    tagged.extend_from_slice(&tiff);
+    tagged.extend_from_slice(&encoded[position..]);
+    Some(tagged)
+}
+
+/// TIFF field types.
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+
+/// Appends a TIFF directory entry holding a single value of type `kind`.
+fn ifd_entry(tiff: &mut Vec<u8>, tag: u16, kind: u16, value: u32) {
+    tiff.extend_from_slice(&tag.to_be_bytes());
+    tiff.extend_from_slice(&kind.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    // Short values are left-justified in the four bytes.
+    match kind {
+        SHORT => tiff.extend_from_slice(&[(value as u16).to_be_bytes(), [0, 0]].concat()),
+        _ => tiff.extend_from_slice(&value.to_be_bytes()),
+    }
+}
+
+fn insert_png_comment(encoded: &[u8], comment: &str) -> Option<Vec<u8>> {
+    // Signature (8 bytes) followed by the IHDR chunk (25 bytes), which must come first.
+    let position = 33;
+    if encoded.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let mut chunk = b"tEXtComment\0".to_vec();
+    chunk.extend_from_slice(comment.as_bytes());
+    let mut tagged = Vec::with_capacity(encoded.len() + chunk.len() + 8);
+    tagged.extend_from_slice(&encoded[..position]);
+    tagged.extend_from_slice(&(chunk.len() as u32 - 4).to_be_bytes());
+    tagged.extend_from_slice(&chunk);
+    tagged.extend_from_slice(&crc32(&chunk).to_be_bytes());
+    tagged.extend_from_slice(&encoded[position..]);
+    Some(tagged)
+}
+
+/// CRC-32 as used by PNG chunks.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
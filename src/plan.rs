@@ -0,0 +1,213 @@
+//! Batch plans written by `--plan-out` and replayed by `--from-plan`: the command line
+//! arguments the plan was made with, and the output planned for each input, as JSON.
+
+use std::{iter::Peekable, path::PathBuf, str::Chars};
+
+use photo_framer::logging::push_json_string;
+
+pub struct Plan {
+    /// Arguments of the run that made the plan, without the program name.
+    pub arguments: Vec<String>,
+    /// Each input with its output path, or why it has none.
+    pub files: Vec<(PathBuf, Result<PathBuf, String>)>,
+}
+
+impl Plan {
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"version\":");
+        push_json_string(&mut out, env!("CARGO_PKG_VERSION"));
+        out.push_str(",\"arguments\":[");
+        for (i, argument) in self.arguments.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            push_json_string(&mut out, argument);
+        }
+        out.push_str("],\"files\":[");
+        for (i, (input, output)) in self.files.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("\n{\"input\":");
+            push_json_string(&mut out, &input.to_string_lossy());
+            match output {
+                Ok(output) => {
+                    out.push_str(",\"output\":");
+                    push_json_string(&mut out, &output.to_string_lossy());
+                }
+                Err(error) => {
+                    out.push_str(",\"output\":null,\"error\":");
+                    push_json_string(&mut out, error);
+                }
+            }
+            out.push('}');
+        }
+        out.push_str("\n]}\n");
+        out
+    }
+
+    /// Parses a plan written by [`Plan::to_json`], possibly edited since. Returns `None`
+    /// if it isn't valid JSON or lacks the `arguments` or `files` of a plan.
+    pub fn parse(json: &str) -> Option<Plan> {
+        let mut chars = json.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() {
+            return None;
+        }
+        let arguments = value
+            .get("arguments")?
+            .as_array()?
+            .iter()
+            .map(|argument| argument.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()?;
+        let files = value
+            .get("files")?
+            .as_array()?
+            .iter()
+            .map(|file| {
+                let input = PathBuf::from(file.get("input")?.as_str()?);
+                let output = match file.get("output") {
+                    Some(Value::String(output)) => Ok(PathBuf::from(output)),
+                    _ => Err(file
+                        .get("error")
+                        .and_then(Value::as_str)
+                        .unwrap_or("no output is planned")
+                        .to_string()),
+                };
+                Some((input, output))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Plan { arguments, files })
+    }
+}
+
+/// The JSON values a plan is made of. Numbers and booleans are only kept as placeholders.
+enum Value {
+    Null,
+    Scalar,
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<Value> {
+    skip_whitespace(chars);
+    match *chars.peek()? {
+        '"' => parse_string(chars).map(Value::String),
+        '[' => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&']').is_some() {
+                return Some(Value::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => {}
+                    ']' => return Some(Value::Array(values)),
+                    _ => return None,
+                }
+            }
+        }
+        '{' => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.next_if_eq(&'}').is_some() {
+                return Some(Value::Object(fields));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                chars.next_if_eq(&':')?;
+                fields.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => {}
+                    '}' => return Some(Value::Object(fields)),
+                    _ => return None,
+                }
+            }
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-.".contains(*c)) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "null" => Some(Value::Null),
+                "true" | "false" => Some(Value::Scalar),
+                _ => word.parse::<f64>().ok().map(|_| Value::Scalar),
+            }
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    chars.next_if_eq(&'"')?;
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                'n' => s.push('\n'),
+                'r' => s.push('\r'),
+                't' => s.push('\t'),
+                'b' => s.push('\u{8}'),
+                'f' => s.push('\u{c}'),
+                'u' => {
+                    let mut code = parse_hex(chars)?;
+                    // Characters outside the basic plane are escaped as a surrogate pair.
+                    if (0xD800..0xDC00).contains(&code) {
+                        chars.next_if_eq(&'\\')?;
+                        chars.next_if_eq(&'u')?;
+                        let low = parse_hex(chars)?;
+                        code = 0x10000 + ((code - 0xD800) << 10) + low.checked_sub(0xDC00)?;
+                    }
+                    s.push(char::from_u32(code)?);
+                }
+                c => s.push(c),
+            },
+            c => s.push(c),
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let digits: String = (0..4).map_while(|_| chars.next()).collect();
+    u32::from_str_radix(&digits, 16).ok()
+}
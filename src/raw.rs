@@ -0,0 +1,53 @@
+//! Camera RAW decoding, delegated to a `dcraw` compatible converter such as `dcraw`
+//! itself or LibRaw's `dcraw_emu`. Without the `raw` feature RAW files are only
+//! recognized, to report that they aren't supported.
+
+use std::path::Path;
+#[cfg(feature = "raw")]
+use std::{env, ffi::OsString, process::Command};
+
+#[cfg(feature = "raw")]
+use image::{
+    DynamicImage, ImageError, ImageFormat,
+    error::{DecodingError, ImageFormatHint},
+};
+
+/// Extensions of the camera RAW files that are framed, compared case-insensitively.
+pub const EXTENSIONS: [&str; 10] = [
+    "arw", "cr2", "cr3", "dng", "nef", "nrw", "orf", "pef", "raf", "rw2",
+];
+
+/// Returns whether `file` has the extension of a camera RAW file.
+pub fn is_raw(file: &Path) -> bool {
+    file.extension().is_some_and(|ext| {
+        EXTENSIONS.contains(&ext.to_string_lossy().to_ascii_lowercase().as_str())
+    })
+}
+
+/// Demosaics `file` with the camera's white balance. The converter is `dcraw` unless
+/// the `PHOTO_FRAMER_DCRAW` environment variable names another.
+#[cfg(feature = "raw")]
+pub fn decode(file: &Path) -> Result<DynamicImage, ImageError> {
+    let converter = env::var_os("PHOTO_FRAMER_DCRAW").unwrap_or_else(|| OsString::from("dcraw"));
+    // `-c` writes the image to standard output as a PPM, `-w` applies the camera's white
+    // balance. The converter also rotates the image according to the camera.
+    let error = |message: String| {
+        ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Name(String::from("camera RAW")),
+            message,
+        ))
+    };
+    let output = Command::new(&converter)
+        .args(["-c", "-w"])
+        .arg(file)
+        .output()
+        .map_err(|e| error(format!("unable to run {}: {e}", converter.display())))?;
+    if !output.status.success() {
+        return Err(error(format!(
+            "{} failed: {}",
+            converter.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    image::load_from_memory_with_format(&output.stdout, ImageFormat::Pnm)
+}
@@ -0,0 +1,257 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use image::{ImageFormat, Rgb, RgbImage};
+
+/// An empty directory for the files of the test `name`.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("photo_framer_cli_{}_{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a `w`x`h` image of `color` to `dir`.
+fn solid_png(dir: &Path, name: &str, (w, h): (u32, u32), color: [u8; 3]) -> PathBuf {
+    let path = dir.join(name);
+    RgbImage::from_pixel(w, h, Rgb(color)).save(&path).unwrap();
+    path
+}
+
+/// Runs photo_framer with `args`, at the default log level.
+fn photo_framer(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_photo_framer"))
+        .args(args)
+        .env_remove("RUST_LOG")
+        .output()
+        .unwrap()
+}
+
+fn arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn assert_close(pixel: &Rgb<u8>, expected: [u8; 3]) {
+    assert!(
+        pixel
+            .0
+            .iter()
+            .zip(expected)
+            .all(|(a, b)| a.abs_diff(b) <= 4),
+        "{:?} is not {expected:?}",
+        pixel.0
+    );
+}
+
+#[test]
+fn convert_keeps_the_native_size_without_a_border() {
+    let dir = test_dir("convert");
+    let input = solid_png(&dir, "a.png", (30, 20), [255, 0, 0]);
+    let out = dir.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let output = photo_framer(&["--convert-only", "-i", arg(&input), "-o", arg(&out), "jpeg"]);
+    assert!(output.status.success(), "{output:?}");
+    let converted = image::open(out.join("a.jpeg")).unwrap().into_rgb8();
+    assert_eq!(converted.dimensions(), (30, 20));
+    for (x, y) in [(0, 0), (29, 19), (15, 10)] {
+        assert_close(converted.get_pixel(x, y), [255, 0, 0]);
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn skip_unsupported_exits_successfully_with_a_warning() {
+    let dir = test_dir("skip_unsupported");
+    let input = dir.join("notes.txt");
+    fs::write(&input, "not an image").unwrap();
+    let out = dir.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let args = ["-i", arg(&input), "-o", arg(&out), "--aspect-ratio", "1:1"];
+    let rejected = photo_framer(&args);
+    assert_eq!(rejected.status.code(), Some(exitcode::CONFIG));
+    let skipped = photo_framer(&[&args[..], &["--skip-unsupported"]].concat());
+    assert!(skipped.status.success(), "{skipped:?}");
+    let notice = String::from_utf8_lossy(&skipped.stderr);
+    assert!(
+        notice.starts_with("Input file's filetype is unsupported.")
+            && notice.ends_with(" files. Skipping it.\n"),
+        "{notice}"
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn png_inputs_are_written_in_the_format_of_their_output_path() {
+    let dir = test_dir("png_to_jpeg");
+    let input = solid_png(&dir, "a.png", (30, 20), [0, 0, 255]);
+    // By the output filetype argument.
+    let out = dir.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let output = photo_framer(&[
+        "-i",
+        arg(&input),
+        "-o",
+        arg(&out),
+        "--aspect-ratio",
+        "1:1",
+        "jpeg",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let written = fs::read(out.join("a.jpeg")).unwrap();
+    assert_eq!(image::guess_format(&written).unwrap(), ImageFormat::Jpeg);
+    // Without one, by the extension of the input.
+    let output = photo_framer(&["-i", arg(&input), "-o", arg(&out), "--aspect-ratio", "1:1"]);
+    assert!(output.status.success(), "{output:?}");
+    let written = fs::read(out.join("a.png")).unwrap();
+    assert_eq!(image::guess_format(&written).unwrap(), ImageFormat::Png);
+    // By the extension of an output path of a jobs file.
+    let jobs = dir.join("jobs.tsv");
+    let framed = dir.join("framed.jpg");
+    fs::write(&jobs, format!("{}\t{}\n", arg(&input), arg(&framed))).unwrap();
+    let output = photo_framer(&["--jobs-file", arg(&jobs), "--aspect-ratio", "1:1"]);
+    assert!(output.status.success(), "{output:?}");
+    let written = fs::read(&framed).unwrap();
+    assert_eq!(image::guess_format(&written).unwrap(), ImageFormat::Jpeg);
+    assert_eq!(
+        image::load_from_memory(&written)
+            .unwrap()
+            .into_rgb8()
+            .dimensions(),
+        (30, 30)
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn fail_on_empty_rejects_folders_without_images() {
+    let dir = test_dir("fail_on_empty");
+    let empty = dir.join("empty");
+    let unsupported = dir.join("unsupported");
+    fs::create_dir_all(&empty).unwrap();
+    fs::create_dir_all(&unsupported).unwrap();
+    fs::write(unsupported.join("notes.txt"), "not an image").unwrap();
+    fs::write(unsupported.join("photo.tiff"), "not accepted").unwrap();
+    let out = dir.join("out");
+    fs::create_dir_all(&out).unwrap();
+    for input in [&empty, &unsupported] {
+        let args = ["-i", arg(input), "-o", arg(&out), "--aspect-ratio", "1:1"];
+        let output = photo_framer(&[&args[..], &["--fail-on-empty"]].concat());
+        assert_eq!(output.status.code(), Some(exitcode::NOINPUT), "{output:?}");
+        let output = photo_framer(&args);
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "No images with an accepted filetype were found.\n"
+        );
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn story_centers_the_photo_on_a_blurred_portrait_canvas() {
+    let dir = test_dir("story");
+    let input = dir.join("a.png");
+    // Red above blue, so the blur differs from the white default border.
+    RgbImage::from_fn(300, 200, |_, y| {
+        if y < 100 {
+            Rgb([255, 0, 0])
+        } else {
+            Rgb([0, 0, 255])
+        }
+    })
+    .save(&input)
+    .unwrap();
+    let out = dir.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let output = photo_framer(&["--story", "-i", arg(&input), "-o", arg(&out), "png"]);
+    assert!(output.status.success(), "{output:?}");
+    let framed = image::open(out.join("a.png")).unwrap().into_rgb8();
+    let (w, h) = framed.dimensions();
+    assert_eq!((w, h), (300, 533));
+    // The photo spans the width, with as much of the blurred fill above as below it.
+    let top = (h - 200) / 2;
+    assert_close(framed.get_pixel(150, top), [255, 0, 0]);
+    assert_close(framed.get_pixel(150, top + 199), [0, 0, 255]);
+    for y in [0, top / 2, h - 1] {
+        assert_ne!(framed.get_pixel(150, y), &Rgb([255, 255, 255]), "at y {y}");
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn jobs_frame_one_input_at_the_sizing_of_each_line() {
+    let dir = test_dir("jobs_sizings");
+    let input = solid_png(&dir, "a.png", (30, 20), [0, 255, 0]);
+    let (square, wide, default) = (
+        dir.join("square.png"),
+        dir.join("wide.png"),
+        dir.join("default.png"),
+    );
+    let jobs = dir.join("jobs.tsv");
+    fs::write(
+        &jobs,
+        format!(
+            "{input}\t{}\t1:1\n  # An indented comment.\n{input}\t{}\t2:1\n{input}\t{}\n",
+            arg(&square),
+            arg(&wide),
+            arg(&default),
+            input = arg(&input)
+        ),
+    )
+    .unwrap();
+    let output = photo_framer(&["--jobs-file", arg(&jobs), "--aspect-ratio", "3:4"]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("invalid job"));
+    assert_eq!(image::image_dimensions(&square).unwrap(), (30, 30));
+    assert_eq!(image::image_dimensions(&wide).unwrap(), (40, 20));
+    assert_eq!(image::image_dimensions(&default).unwrap(), (30, 40));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn plans_replay_from_another_directory_with_run_options() {
+    let dir = test_dir("plan_replay");
+    fs::create_dir_all(dir.join("in")).unwrap();
+    fs::create_dir_all(dir.join("out")).unwrap();
+    fs::create_dir_all(dir.join("elsewhere")).unwrap();
+    solid_png(&dir.join("in"), "a.png", (30, 20), [255, 0, 0]);
+    let run = |cwd: &Path, args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_photo_framer"))
+            .current_dir(cwd)
+            .args(args)
+            .env_remove("RUST_LOG")
+            .output()
+            .unwrap()
+    };
+    let args = [
+        "-i",
+        "in",
+        "-o",
+        "out",
+        "--aspect-ratio",
+        "1:1",
+        "--plan-out",
+        "plan.json",
+    ];
+    let output = run(&dir, &args);
+    assert!(output.status.success(), "{output:?}");
+    assert!(!dir.join("out/a.png").exists());
+    // Relative to `dir`, not to where the plan is replayed.
+    let output = run(
+        &dir.join("elsewhere"),
+        &["--from-plan", "../plan.json", "--yes", "-v", "--jobs", "2"],
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Framed 1 of 1 images."));
+    assert_eq!(
+        image::image_dimensions(dir.join("out/a.png")).unwrap(),
+        (30, 30)
+    );
+    // What is framed into where comes from the plan alone.
+    let output = run(&dir, &["--from-plan", "plan.json", "-o", "elsewhere"]);
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,48 @@
+use image::{DynamicImage, Rgb, RgbImage};
+use photo_framer::{
+    framer::{FramerError, FramerOptions, Sizing, frame_image_buffer},
+    lut::Lut,
+};
+
+#[test]
+fn frames_a_decoded_image_through_the_library() {
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(30, 20, Rgb([255, 0, 0])));
+    let framed = frame_image_buffer(
+        img,
+        Sizing::AspectRatio(1.0, 1.0),
+        &FramerOptions::default(),
+    )
+    .unwrap();
+    assert_eq!((framed.width, framed.height), (30, 30));
+    assert_eq!(framed.photo, (0, 5, 30, 20));
+    assert_eq!(framed.image.get_pixel(15, 15), &Rgb([255, 0, 0]));
+    assert_eq!(framed.image.get_pixel(15, 0), &Rgb([255, 255, 255]));
+}
+
+#[test]
+fn lut_grades_the_framed_image_and_reports_invalid_tables() {
+    // Swaps red and blue.
+    let mut cube = String::from("LUT_3D_SIZE 2\n");
+    for b in 0..2 {
+        for g in 0..2 {
+            for r in 0..2 {
+                cube.push_str(&format!("{b} {g} {r}\n"));
+            }
+        }
+    }
+    let options = FramerOptions {
+        lut: Some(Lut::parse(&cube).unwrap()),
+        ..FramerOptions::default()
+    };
+    let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(20, 20, Rgb([255, 0, 0])));
+    let framed = frame_image_buffer(img, Sizing::AspectRatio(1.0, 1.0), &options).unwrap();
+    assert_eq!(framed.image.get_pixel(10, 10), &Rgb([0, 0, 255]));
+    assert!(matches!(
+        Lut::parse("LUT_3D_SIZE 2\n0 0 0\n"),
+        Err(FramerError::InvalidLut(message)) if message.contains("expected 8 entries")
+    ));
+    assert!(matches!(
+        Lut::load("does/not/exist.cube".as_ref()),
+        Err(FramerError::Io(_))
+    ));
+}